@@ -1,3 +1,4 @@
+use crate::{JobDetails, JobSettings};
 use serde::{Deserialize, Serialize};
 use stackable_operator::k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
 use stackable_operator::k8s_openapi::chrono::Utc;
@@ -30,12 +31,20 @@ pub struct ClusterRef {
 pub struct DruidConnectionSpec {
     pub superset: ClusterRef,
     pub druid: ClusterRef,
+    /// Retries, deadline and cleanup of the import Job
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<JobSettings>,
 }
 
 impl DruidConnection {
     pub fn job_name(&self) -> String {
         format!("{}-import", self.name())
     }
+
+    /// The ConfigMap with the generated files of the import Job
+    pub fn job_config_map_name(&self) -> String {
+        format!("{}-import-config", self.name())
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, JsonSchema)]
@@ -44,6 +53,14 @@ pub struct DruidConnectionStatus {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub started_at: Option<Time>,
     pub condition: DruidConnectionStatusCondition,
+    /// The dependency which keeps the connection in `Pending`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<DruidConnectionPendingReason>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// The finished import Job
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<JobDetails>,
 }
 
 impl DruidConnectionStatus {
@@ -51,12 +68,26 @@ impl DruidConnectionStatus {
         Self {
             started_at: Some(Time(Utc::now())),
             condition: DruidConnectionStatusCondition::Pending,
+            reason: None,
+            message: None,
+            job: None,
         }
     }
 
+    pub fn pending(&self, reason: DruidConnectionPendingReason, message: String) -> Self {
+        let mut new = self.clone();
+        new.condition = DruidConnectionStatusCondition::Pending;
+        new.reason = Some(reason);
+        new.message = Some(message);
+        new
+    }
+
     pub fn importing(&self) -> Self {
         let mut new = self.clone();
         new.condition = DruidConnectionStatusCondition::Importing;
+        new.reason = None;
+        new.message = None;
+        new.job = None;
         new
     }
 
@@ -71,6 +102,12 @@ impl DruidConnectionStatus {
         new.condition = DruidConnectionStatusCondition::Failed;
         new
     }
+
+    pub fn with_job(&self, job: JobDetails) -> Self {
+        let mut new = self.clone();
+        new.job = Some(job);
+        new
+    }
 }
 
 impl Default for DruidConnectionStatus {
@@ -86,3 +123,15 @@ pub enum DruidConnectionStatusCondition {
     Ready,
     Failed,
 }
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, JsonSchema, PartialEq, Serialize)]
+pub enum DruidConnectionPendingReason {
+    // The referenced SupersetDB does not exist
+    SupersetDBNotFound,
+    // The referenced SupersetDB is not initialized yet
+    SupersetDBNotReady,
+    // The discovery ConfigMap of the Druid cluster does not exist
+    DruidConfigMapNotFound,
+    // The discovery ConfigMap of the Druid cluster contains no `DRUID_SQLALCHEMY`
+    DruidConnectionStringMissing,
+}
@@ -1,13 +1,19 @@
 pub mod druidconnection;
 pub mod supersetdb;
+pub mod supersetdbbackup;
+pub mod supersetdbrestore;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::num::ParseIntError;
 
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use serde::{Deserialize, Serialize};
-use snafu::Snafu;
+use snafu::{OptionExt, Snafu};
+use stackable_operator::k8s_openapi::api::core::v1::LocalObjectReference;
+use stackable_operator::k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use stackable_operator::k8s_openapi::chrono::Utc;
 use stackable_operator::kube::runtime::reflector::ObjectRef;
-use stackable_operator::kube::CustomResource;
+use stackable_operator::kube::{CustomResource, ResourceExt};
 use stackable_operator::product_config::flask_app_config_writer::{
     FlaskAppConfigOptions, PythonType,
 };
@@ -15,6 +21,8 @@ use stackable_operator::product_config_utils::{ConfigError, Configuration};
 use stackable_operator::role_utils::{Role, RoleGroupRef};
 use stackable_operator::schemars::{self, JsonSchema};
 use strum::{Display, EnumIter, EnumString, IntoEnumIterator};
+use supersetdb::DeletionPolicy;
+use supersetdbbackup::DatabaseDumpConfig;
 
 pub const APP_NAME: &str = "superset";
 pub const MANAGED_BY: &str = "superset-operator";
@@ -26,6 +34,20 @@ pub const SUPERSET_CONFIG_FILENAME: &str = "superset_config.py";
 pub enum Error {
     #[snafu(display("invalid int config value"))]
     InvalidIntConfigValue { source: ParseIntError },
+    #[snafu(display(
+        "no Secret is referenced for the credential {credential}, either set credentialsSecret or reference it in credentials"
+    ))]
+    MissingCredential { credential: String },
+    #[snafu(display(
+        "metadataDatabaseTls only applies to the sqlalchemyDatabaseUri credential, set metadataDatabase.tls instead"
+    ))]
+    AmbiguousMetadataDatabaseTls,
+    #[snafu(display(
+        "the config snippet {key} is not the file name of a Python module, e.g. `my_module.py`"
+    ))]
+    InvalidConfigSnippetKey { key: String },
+    #[snafu(display("the key {key} is used by more than one config snippet"))]
+    DuplicateConfigSnippetKey { key: String },
 }
 
 #[derive(Display, EnumIter, EnumString)]
@@ -35,6 +57,19 @@ pub enum SupersetConfigOptions {
     SqlalchemyDatabaseUri,
     StatsLogger,
     RowLimit,
+    FeatureFlags,
+    SupersetWebserverTimeout,
+    SqllabTimeout,
+    SqllabAsyncTimeLimitSec,
+    SqlMaxRow,
+    DisplayMaxRow,
+    SamplesRowLimit,
+    CsvExport,
+    BabelDefaultLocale,
+    Languages,
+    MapboxApiKey,
+    WtfCsrfEnabled,
+    TalismanEnabled,
 }
 
 impl SupersetConfigOptions {
@@ -44,6 +79,43 @@ impl SupersetConfigOptions {
     fn config_type_to_string(&self, superset_config: &SupersetConfig) -> Option<String> {
         match self {
             SupersetConfigOptions::RowLimit => superset_config.row_limit.map(|v| v.to_string()),
+            SupersetConfigOptions::SupersetWebserverTimeout => {
+                superset_config.webserver_timeout.map(|v| v.to_string())
+            }
+            SupersetConfigOptions::SqllabTimeout => {
+                superset_config.sqllab_timeout.map(|v| v.to_string())
+            }
+            SupersetConfigOptions::SqllabAsyncTimeLimitSec => superset_config
+                .sqllab_async_time_limit_sec
+                .map(|v| v.to_string()),
+            SupersetConfigOptions::SqlMaxRow => superset_config.sql_max_row.map(|v| v.to_string()),
+            SupersetConfigOptions::DisplayMaxRow => {
+                superset_config.display_max_row.map(|v| v.to_string())
+            }
+            SupersetConfigOptions::SamplesRowLimit => {
+                superset_config.samples_row_limit.map(|v| v.to_string())
+            }
+            SupersetConfigOptions::CsvExport => superset_config
+                .csv_export_encoding
+                .as_ref()
+                .map(|encoding| format!("{{'encoding': {}}}", python_string(encoding))),
+            SupersetConfigOptions::BabelDefaultLocale => superset_config
+                .babel_default_locale
+                .map(|locale| locale.to_string()),
+            SupersetConfigOptions::Languages => superset_config
+                .languages
+                .as_ref()
+                .map(|languages| languages_dict(languages)),
+            SupersetConfigOptions::MapboxApiKey => superset_config
+                .mapbox_api_key
+                .as_ref()
+                .map(|_| format!("os.environ.get('{MAPBOX_API_KEY_ENV}')")),
+            SupersetConfigOptions::WtfCsrfEnabled => {
+                superset_config.wtf_csrf_enabled.map(|v| v.to_string())
+            }
+            SupersetConfigOptions::TalismanEnabled => {
+                superset_config.talisman_enabled.map(|v| v.to_string())
+            }
             _ => None,
         }
     }
@@ -56,11 +128,49 @@ impl FlaskAppConfigOptions for SupersetConfigOptions {
             SupersetConfigOptions::SecretKey => PythonType::Expression,
             SupersetConfigOptions::SqlalchemyDatabaseUri => PythonType::Expression,
             SupersetConfigOptions::StatsLogger => PythonType::Expression,
+            // A dict literal, see `feature_flags_dict`
+            SupersetConfigOptions::FeatureFlags => PythonType::Expression,
+            SupersetConfigOptions::SupersetWebserverTimeout => PythonType::IntLiteral,
+            SupersetConfigOptions::SqllabTimeout => PythonType::IntLiteral,
+            SupersetConfigOptions::SqllabAsyncTimeLimitSec => PythonType::IntLiteral,
+            SupersetConfigOptions::SqlMaxRow => PythonType::IntLiteral,
+            SupersetConfigOptions::DisplayMaxRow => PythonType::IntLiteral,
+            SupersetConfigOptions::SamplesRowLimit => PythonType::IntLiteral,
+            SupersetConfigOptions::CsvExport => PythonType::Expression,
+            SupersetConfigOptions::BabelDefaultLocale => PythonType::StringLiteral,
+            SupersetConfigOptions::Languages => PythonType::Expression,
+            SupersetConfigOptions::MapboxApiKey => PythonType::Expression,
+            SupersetConfigOptions::WtfCsrfEnabled => PythonType::BoolLiteral,
+            SupersetConfigOptions::TalismanEnabled => PythonType::BoolLiteral,
         }
     }
 }
 
+/// The environment variable which contains the `mapboxApiKey`
+pub const MAPBOX_API_KEY_ENV: &str = "MAPBOX_API_KEY";
+
+/// Returns the string as a Python string literal
+fn python_string(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Returns the `LANGUAGES` dict with the flags and names Superset uses for the given locales
+fn languages_dict(languages: &[SupersetLocale]) -> String {
+    let entries = languages
+        .iter()
+        .map(|locale| {
+            let (flag, name) = locale.flag_and_name();
+            format!("'{locale}': {{'flag': '{flag}', 'name': '{name}'}}")
+        })
+        .collect::<Vec<_>>();
+    format!("{{{}}}", entries.join(", "))
+}
+
 pub const HTTP_PORT: &str = "http";
+pub const HTTPS_PORT: &str = "https";
+
+/// The directory in which the webserver certificate is mounted
+pub const TLS_DIR: &str = "/stackable/tls";
 
 #[derive(Clone, CustomResource, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
 #[kube(
@@ -85,15 +195,283 @@ pub struct SupersetClusterSpec {
     /// Desired Superset version
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub version: Option<String>,
+    /// Version of the statsd exporter sidecar which exposes the metrics of Superset to
+    /// Prometheus. The sidecar is only deployed if a version is given.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub statsd_exporter_version: Option<String>,
-    pub credentials_secret: String,
+    /// Where Superset sends its statsd metrics and how the sidecar maps them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub statsd: Option<StatsdConfig>,
+    /// Secret containing all credentials under well-known keys, see `credentials` to reference
+    /// them individually instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials_secret: Option<String>,
+    /// References to individual credentials which take precedence over `credentialsSecret`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<CredentialsRefs>,
+    /// If set, the operator creates the `credentialsSecret` with a random secret key and admin
+    /// password if it does not exist yet. It defaults to `<cluster name>-credentials`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generate_credentials: Option<GeneratedCredentials>,
     #[serde(default)]
     pub load_examples_on_init: Option<bool>,
+    /// Dump the metadata database into a volume before the schema is migrated to a new version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_before_migration: Option<DatabaseDumpConfig>,
+    /// Whether the Superset tables are dropped when the SupersetDB of this cluster is deleted, see
+    /// the `deletionPolicy` of the SupersetDB
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database_deletion_policy: Option<DeletionPolicy>,
+    /// Adopt an existing schema without creating the admin user or loading the examples
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adopt_existing_schema: Option<bool>,
+    /// Retries, deadline and cleanup of the Jobs which initialize and migrate the database
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database_jobs: Option<JobSettings>,
+    /// Connection details of the metadata database from which the SQLAlchemy URI is assembled,
+    /// takes precedence over the `sqlalchemyDatabaseUri` credential
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_database: Option<MetadataDatabase>,
+    /// TLS settings of the metadata database if its URI is taken from the `sqlalchemyDatabaseUri`
+    /// credential, cannot be combined with `metadataDatabase`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_database_tls: Option<MetadataDatabaseTls>,
+    /// Serve the web UI over HTTPS with the certificate from a `secret` of type
+    /// `kubernetes.io/tls`, or from a `secretClass` of the Stackable secret-operator which issues a
+    /// certificate for each pod
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<WebserverTls>,
+    /// The images of the Superset pods and Jobs and how they are pulled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product_image: Option<ProductImage>,
+    /// Additional Python packages, e.g. SQLAlchemy dialects, which are installed into the pods
+    /// and Jobs before Superset is started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub python_packages: Option<PythonPackages>,
+    /// Python modules from ConfigMaps which are added to the generated `superset_config.py` in
+    /// the given order, e.g. to define a custom security manager
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub config_snippets: Vec<ConfigSnippet>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub nodes: Option<Role<SupersetConfig>>,
 }
 
+pub const DEFAULT_IMAGE_REPO: &str = "docker.stackable.tech/stackable";
+pub const DEFAULT_STATSD_EXPORTER_IMAGE_REPO: &str = "docker.stackable.tech/prom";
+pub const DEFAULT_DATABASE_CLIENT_IMAGE_REPO: &str = "docker.io/library";
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductImage {
+    /// Complete name of a custom Superset image, e.g. with additional database drivers. It must
+    /// be based on the Stackable image of the Superset `version`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom: Option<String>,
+    /// Repository from which the Superset, statsd exporter and database client images are pulled,
+    /// e.g. a mirror in an internal registry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    /// Version of the Stackable image which is appended to the Superset version in the image
+    /// tag, defaults to `stackable1`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stackable_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_policy: Option<PullPolicy>,
+    /// Secrets with the credentials of the registries, added to all pods
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_secrets: Option<Vec<PullSecret>>,
+}
+
+/// Reference to a Secret of type `kubernetes.io/dockerconfigjson` in the namespace of the pods
+#[derive(Clone, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullSecret {
+    pub name: String,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Display, Eq, Hash, JsonSchema, PartialEq, Serialize)]
+pub enum PullPolicy {
+    IfNotPresent,
+    Always,
+    Never,
+}
+
+impl ProductImage {
+    pub fn superset_image(&self, superset_version: &str) -> String {
+        self.custom.clone().unwrap_or_else(|| {
+            format!(
+                "{}/superset:{superset_version}-{}",
+                self.repo.as_deref().unwrap_or(DEFAULT_IMAGE_REPO),
+                self.stackable_version.as_deref().unwrap_or("stackable1")
+            )
+        })
+    }
+
+    pub fn statsd_exporter_image(&self, statsd_exporter_version: &str) -> String {
+        format!(
+            "{}/statsd-exporter:{statsd_exporter_version}",
+            self.repo
+                .as_deref()
+                .unwrap_or(DEFAULT_STATSD_EXPORTER_IMAGE_REPO)
+        )
+    }
+
+    /// The image of the backup and restore Jobs, unless the dump sets its own image
+    pub fn database_client_image(&self, db_type: DatabaseType) -> String {
+        format!(
+            "{}/{}",
+            self.repo
+                .as_deref()
+                .unwrap_or(DEFAULT_DATABASE_CLIENT_IMAGE_REPO),
+            db_type.default_client_image()
+        )
+    }
+
+    pub fn pull_secrets(&self) -> Option<Vec<LocalObjectReference>> {
+        self.pull_secrets.as_ref().map(|pull_secrets| {
+            pull_secrets
+                .iter()
+                .map(|pull_secret| LocalObjectReference {
+                    name: Some(pull_secret.name.clone()),
+                })
+                .collect()
+        })
+    }
+
+    pub fn pull_policy(&self) -> PullPolicy {
+        self.pull_policy.unwrap_or(PullPolicy::IfNotPresent)
+    }
+}
+
+/// The port on which the statsd exporter sidecar receives the metrics by default
+pub const STATSD_PORT: u16 = 9125;
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsdConfig {
+    /// Mapping config of the statsd exporter sidecar which replaces the default mapping of the
+    /// Superset metric names to labelled Prometheus metrics
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mapping_config: Option<String>,
+    /// Host to which Superset sends its metrics, defaults to the statsd exporter sidecar
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// Port to which Superset sends its metrics and on which the sidecar receives them, defaults
+    /// to 9125
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+}
+
+/// The directory on the `PYTHONPATH` into which the `pythonPackages` are installed
+pub const PYTHON_PACKAGES_DIR: &str = "/stackable/python-packages";
+/// The directory in which the wheelhouse of the `pythonPackages` is mounted
+pub const WHEELHOUSE_DIR: &str = "/stackable/wheelhouse";
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PythonPackages {
+    /// Requirement specifiers which are passed to pip, e.g. `trino==0.313.0`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub packages: Vec<String>,
+    /// Volume with wheel files, either a `persistentVolumeClaim` with the wheel files in its root
+    /// directory or a `configMap` with the wheel files as binary data. If it is given, no package
+    /// index is accessed and all wheels in it are installed unless `packages` are listed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wheelhouse: Option<Wheelhouse>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Wheelhouse {
+    // A PersistentVolumeClaim with the wheel files in its root directory
+    #[serde(rename_all = "camelCase")]
+    PersistentVolumeClaim {
+        claim_name: String,
+    },
+    // A ConfigMap with the wheel files as binary data
+    ConfigMap {
+        name: String,
+    },
+}
+
+/// The directory on the `PYTHONPATH` of the webserver in which the `configSnippets` are mounted
+pub const CONFIG_SNIPPETS_DIR: &str = "/stackable/config-snippets";
+
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigSnippet {
+    /// Name of the ConfigMap in the namespace of the cluster
+    pub config_map: String,
+    /// Key of the Python module in the ConfigMap, e.g. `security_manager.py`. The keys of all
+    /// snippets must be distinct.
+    pub key: String,
+    /// How the module is added to `superset_config.py`, defaults to `Import`. `Import` imports all
+    /// its public names, `Append` executes it after the generated settings so that it can read and
+    /// override them.
+    #[serde(default)]
+    pub mode: ConfigSnippetMode,
+}
+
+impl ConfigSnippet {
+    /// The name under which the module is imported, fails if the key is not a valid file name of
+    /// a module
+    pub fn module_name(&self) -> Result<&str, Error> {
+        self.key
+            .strip_suffix(".py")
+            .filter(|name| {
+                let mut chars = name.chars();
+                chars
+                    .next()
+                    .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+                    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+            })
+            .with_context(|| InvalidConfigSnippetKeySnafu {
+                key: self.key.clone(),
+            })
+    }
+}
+
+/// Ensures that every config snippet can be imported under a distinct module name
+fn validate_config_snippets(snippets: &[ConfigSnippet]) -> Result<&[ConfigSnippet], Error> {
+    let mut keys = HashSet::new();
+    for snippet in snippets {
+        snippet.module_name()?;
+        if !keys.insert(&snippet.key) {
+            return DuplicateConfigSnippetKeySnafu {
+                key: snippet.key.clone(),
+            }
+            .fail();
+        }
+    }
+    Ok(snippets)
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, JsonSchema, PartialEq, Serialize)]
+pub enum ConfigSnippetMode {
+    // All public names of the module are imported, e.g. a custom `CUSTOM_SECURITY_MANAGER`
+    Import,
+    // The module is executed in `superset_config.py`, so it can read and override the settings
+    // generated before
+    Append,
+}
+
+impl Default for ConfigSnippetMode {
+    fn default() -> Self {
+        ConfigSnippetMode::Import
+    }
+}
+
+/// Where the certificate and private key of the webserver come from. Both sources provide the
+/// files `tls.crt` and `tls.key`.
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WebserverTls {
+    // A Secret of type `kubernetes.io/tls`, e.g. issued by cert-manager
+    Secret { name: String },
+    // A SecretClass of the Stackable secret-operator which issues a certificate for each pod
+    SecretClass(String),
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SupersetCredentials {
@@ -118,6 +496,413 @@ pub struct Connections {
     pub sqlalchemy_database_uri: String,
 }
 
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, JsonSchema, PartialEq, Serialize)]
+pub enum DatabaseType {
+    #[serde(rename = "postgresql")]
+    Postgresql,
+    #[serde(rename = "mysql")]
+    Mysql,
+}
+
+impl DatabaseType {
+    pub fn default_port(&self) -> u16 {
+        match self {
+            DatabaseType::Postgresql => 5432,
+            DatabaseType::Mysql => 3306,
+        }
+    }
+
+    /// Name and tag of the official image with the client tools, without the repository
+    pub fn default_client_image(&self) -> &'static str {
+        match self {
+            DatabaseType::Postgresql => "postgres:14",
+            DatabaseType::Mysql => "mysql:8.0",
+        }
+    }
+
+    pub fn dump_file_extension(&self) -> &'static str {
+        match self {
+            DatabaseType::Postgresql => "dump",
+            DatabaseType::Mysql => "sql",
+        }
+    }
+}
+
+/// Characters which are kept as they are in the components of a URI, see RFC 3986
+const URI_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Connection details of the metadata database
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataDatabase {
+    pub db_type: DatabaseType,
+    pub host: String,
+    /// Defaults to the standard port of `dbType`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    pub database: String,
+    /// Additional query parameters of the URI, the TLS parameters are set by `tls`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub options: BTreeMap<String, String>,
+    pub username: SecretKeyRef,
+    pub password: SecretKeyRef,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<MetadataDatabaseTls>,
+}
+
+/// TLS settings of the connection to the metadata database, the certificates are mounted into
+/// the webserver pods and the Jobs
+#[derive(Clone, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataDatabaseTls {
+    /// `sslmode` for PostgreSQL, e.g. `verify-full`, or `ssl_mode` for MySQL, e.g.
+    /// `VERIFY_IDENTITY`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssl_mode: Option<String>,
+    /// The CA certificate which the server certificate is verified against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca: Option<CaCertificate>,
+    /// Name of a Secret of type `kubernetes.io/tls` with the client certificate and key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_certificate_secret: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CaCertificate {
+    ConfigMap {
+        name: String,
+        #[serde(default = "CaCertificate::default_key")]
+        key: String,
+    },
+    Secret {
+        name: String,
+        #[serde(default = "CaCertificate::default_key")]
+        key: String,
+    },
+}
+
+impl CaCertificate {
+    fn default_key() -> String {
+        "ca.crt".to_string()
+    }
+}
+
+impl MetadataDatabaseTls {
+    /// The directory in which the CA certificate is mounted as `ca.crt`
+    pub const CA_DIR: &'static str = "/stackable/metadata-db-tls/ca";
+    pub const CA_FILE: &'static str = "/stackable/metadata-db-tls/ca/ca.crt";
+    /// The directory in which the client certificate Secret is mounted
+    pub const CLIENT_CERTIFICATE_DIR: &'static str = "/stackable/metadata-db-tls/client";
+
+    /// The query of the SQLAlchemy URI which configures TLS, percent-encoded and without the
+    /// leading `?`
+    pub fn uri_query(&self, db_type: DatabaseType) -> String {
+        query(&self.uri_options(db_type))
+    }
+
+    /// The query parameters of the SQLAlchemy URI which configure TLS
+    fn uri_options(&self, db_type: DatabaseType) -> BTreeMap<String, String> {
+        let (ssl_mode, ca, cert, key) = match db_type {
+            DatabaseType::Postgresql => ("sslmode", "sslrootcert", "sslcert", "sslkey"),
+            DatabaseType::Mysql => ("ssl_mode", "ssl_ca", "ssl_cert", "ssl_key"),
+        };
+        let mut options = BTreeMap::new();
+        if let Some(mode) = &self.ssl_mode {
+            options.insert(ssl_mode.to_string(), mode.clone());
+        }
+        if self.ca.is_some() {
+            options.insert(ca.to_string(), Self::CA_FILE.to_string());
+        }
+        if self.client_certificate_secret.is_some() {
+            options.insert(
+                cert.to_string(),
+                format!("{}/tls.crt", Self::CLIENT_CERTIFICATE_DIR),
+            );
+            options.insert(
+                key.to_string(),
+                format!("{}/tls.key", Self::CLIENT_CERTIFICATE_DIR),
+            );
+        }
+        options
+    }
+}
+
+impl MetadataDatabase {
+    /// The start of the SQLAlchemy URI up to the user info, e.g. `postgresql://`
+    pub fn uri_scheme(&self) -> String {
+        match self.db_type {
+            DatabaseType::Postgresql => "postgresql://",
+            DatabaseType::Mysql => "mysql://",
+        }
+        .to_string()
+    }
+
+    /// The rest of the SQLAlchemy URI after the password, e.g.
+    /// `@postgresql:5432/superset?sslmode=require`, with all components percent-encoded.
+    ///
+    /// The result only contains unreserved characters and the delimiters `%:/@?&=[]`.
+    pub fn uri_location(&self) -> String {
+        let host = if self.host.contains(':') {
+            // IPv6 address
+            format!("[{}]", self.host)
+        } else {
+            utf8_percent_encode(&self.host, URI_COMPONENT).to_string()
+        };
+        let mut location = format!(
+            "@{host}:{}/{}",
+            self.port.unwrap_or_else(|| self.db_type.default_port()),
+            utf8_percent_encode(&self.database, URI_COMPONENT)
+        );
+        let mut options = self.options.clone();
+        if let Some(tls) = &self.tls {
+            options.extend(tls.uri_options(self.db_type));
+        }
+        if !options.is_empty() {
+            location.push('?');
+            location.push_str(&query(&options));
+        }
+        location
+    }
+}
+
+/// Returns the percent-encoded query of a URI with the given parameters
+fn query(options: &BTreeMap<String, String>) -> String {
+    options
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(key, URI_COMPONENT),
+                utf8_percent_encode(value, URI_COMPONENT)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Where the SQLAlchemy URI of the metadata database comes from
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MetadataDatabaseConnection {
+    /// The complete URI is stored in a Secret, the TLS parameters are appended to it
+    Uri {
+        uri: SecretKeyRef,
+        tls: Option<MetadataDatabaseTls>,
+    },
+    /// The URI is assembled from the connection details and the credentials they reference
+    Assembled(MetadataDatabase),
+}
+
+impl MetadataDatabaseConnection {
+    pub fn tls(&self) -> Option<&MetadataDatabaseTls> {
+        match self {
+            MetadataDatabaseConnection::Uri { tls, .. } => tls.as_ref(),
+            MetadataDatabaseConnection::Assembled(database) => database.tls.as_ref(),
+        }
+    }
+
+    pub fn secret_key_refs(&self) -> Vec<&SecretKeyRef> {
+        match self {
+            MetadataDatabaseConnection::Uri { uri, .. } => vec![uri],
+            MetadataDatabaseConnection::Assembled(database) => {
+                vec![&database.username, &database.password]
+            }
+        }
+    }
+}
+
+/// The admin user of a generated credentials Secret. The SQLAlchemy URI cannot be generated and
+/// must be given in `metadataDatabase` or referenced in `credentials`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeneratedCredentials {
+    /// Defaults to `admin`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_username: Option<String>,
+    /// Defaults to `Superset`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_firstname: Option<String>,
+    /// Defaults to `Admin`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_lastname: Option<String>,
+    /// Defaults to `admin@superset.com`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_email: Option<String>,
+}
+
+impl GeneratedCredentials {
+    /// The content of the generated Secret under the well-known keys, given the random values
+    pub fn secret_data(
+        &self,
+        secret_key: String,
+        admin_password: String,
+    ) -> BTreeMap<String, String> {
+        [
+            ("connections.secretKey", secret_key),
+            (
+                "adminUser.username",
+                self.admin_username
+                    .clone()
+                    .unwrap_or_else(|| "admin".into()),
+            ),
+            (
+                "adminUser.firstname",
+                self.admin_firstname
+                    .clone()
+                    .unwrap_or_else(|| "Superset".into()),
+            ),
+            (
+                "adminUser.lastname",
+                self.admin_lastname
+                    .clone()
+                    .unwrap_or_else(|| "Admin".into()),
+            ),
+            (
+                "adminUser.email",
+                self.admin_email
+                    .clone()
+                    .unwrap_or_else(|| "admin@superset.com".into()),
+            ),
+            ("adminUser.password", admin_password),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect()
+    }
+}
+
+/// A reference to a key in a Secret in the namespace of the cluster
+#[derive(Clone, Debug, Deserialize, Eq, Hash, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretKeyRef {
+    pub name: String,
+    pub key: String,
+}
+
+impl SecretKeyRef {
+    pub fn new(name: impl Into<String>, key: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            key: key.into(),
+        }
+    }
+}
+
+/// References to the individual credentials. Credentials which are not referenced here are taken
+/// from the `credentialsSecret`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialsRefs {
+    /// The Flask secret key, used to sign session cookies and encrypt stored passwords
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_key: Option<SecretKeyRef>,
+    /// The secret key before it was rotated, needed to re-encrypt the stored secrets with the new
+    /// secret key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_secret_key: Option<SecretKeyRef>,
+    /// The SQLAlchemy URI of the metadata database, ignored if `metadataDatabase` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sqlalchemy_database_uri: Option<SecretKeyRef>,
+    /// The admin user which is created when the database is initialized
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_user: Option<AdminUserRefs>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminUserRefs {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<SecretKeyRef>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub firstname: Option<SecretKeyRef>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lastname: Option<SecretKeyRef>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<SecretKeyRef>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<SecretKeyRef>,
+}
+
+/// The Secret keys of all credentials, after the references in [`CredentialsRefs`] are merged
+/// with the well-known keys of the `credentialsSecret`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ResolvedCredentials {
+    pub secret_key: SecretKeyRef,
+    /// Only required while the secret key is rotated, so its existence is not checked in advance
+    pub previous_secret_key: Option<SecretKeyRef>,
+    pub metadata_database: MetadataDatabaseConnection,
+    pub admin_username: SecretKeyRef,
+    pub admin_firstname: SecretKeyRef,
+    pub admin_lastname: SecretKeyRef,
+    pub admin_email: SecretKeyRef,
+    pub admin_password: SecretKeyRef,
+}
+
+impl ResolvedCredentials {
+    pub fn resolve(
+        credentials_secret: Option<&str>,
+        credentials: Option<&CredentialsRefs>,
+        metadata_database: Option<&MetadataDatabase>,
+        metadata_database_tls: Option<&MetadataDatabaseTls>,
+    ) -> Result<Self, Error> {
+        let refs = credentials.cloned().unwrap_or_default();
+        let admin_user = refs.admin_user.unwrap_or_default();
+        let resolve = |reference: Option<SecretKeyRef>, key: &str| {
+            reference
+                .or_else(|| credentials_secret.map(|secret| SecretKeyRef::new(secret, key)))
+                .context(MissingCredentialSnafu { credential: key })
+        };
+
+        Ok(Self {
+            secret_key: resolve(refs.secret_key, "connections.secretKey")?,
+            previous_secret_key: resolve(refs.previous_secret_key, "connections.previousSecretKey")
+                .ok(),
+            metadata_database: match (metadata_database, metadata_database_tls) {
+                (Some(_), Some(_)) => return AmbiguousMetadataDatabaseTlsSnafu.fail(),
+                (Some(database), None) => MetadataDatabaseConnection::Assembled(database.clone()),
+                (None, tls) => MetadataDatabaseConnection::Uri {
+                    uri: resolve(
+                        refs.sqlalchemy_database_uri,
+                        "connections.sqlalchemyDatabaseUri",
+                    )?,
+                    tls: tls.cloned(),
+                },
+            },
+            admin_username: resolve(admin_user.username, "adminUser.username")?,
+            admin_firstname: resolve(admin_user.firstname, "adminUser.firstname")?,
+            admin_lastname: resolve(admin_user.lastname, "adminUser.lastname")?,
+            admin_email: resolve(admin_user.email, "adminUser.email")?,
+            admin_password: resolve(admin_user.password, "adminUser.password")?,
+        })
+    }
+
+    /// All referenced Secret keys
+    pub fn secret_key_refs(&self) -> Vec<&SecretKeyRef> {
+        let mut refs = vec![&self.secret_key];
+        refs.extend(self.metadata_database.secret_key_refs());
+        refs.extend([
+            &self.admin_username,
+            &self.admin_firstname,
+            &self.admin_lastname,
+            &self.admin_email,
+            &self.admin_password,
+        ]);
+        refs
+    }
+
+    /// Returns true if the given Secret contains any of the credentials
+    pub fn references_secret(&self, secret_name: &str) -> bool {
+        self.secret_key_refs()
+            .into_iter()
+            .chain(&self.previous_secret_key)
+            .any(|secret_key_ref| secret_key_ref.name == secret_name)
+    }
+}
+
 #[derive(
     Clone, Debug, Deserialize, Display, EnumIter, Eq, Hash, JsonSchema, PartialEq, Serialize,
 )]
@@ -130,10 +915,209 @@ pub enum SupersetRole {
 #[serde(rename_all = "camelCase")]
 pub struct SupersetConfig {
     pub row_limit: Option<i32>,
+    /// Timeout of the webserver for requests in seconds (`SUPERSET_WEBSERVER_TIMEOUT`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webserver_timeout: Option<i32>,
+    /// Timeout of synchronous SQL Lab queries in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sqllab_timeout: Option<i32>,
+    /// Time limit of asynchronous SQL Lab queries in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sqllab_async_time_limit_sec: Option<i32>,
+    /// Maximum number of rows returned by a SQL Lab query
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sql_max_row: Option<i32>,
+    /// Maximum number of rows displayed in SQL Lab
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_max_row: Option<i32>,
+    /// Maximum number of rows shown as samples of a dataset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples_row_limit: Option<i32>,
+    /// Encoding of exported CSV files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub csv_export_encoding: Option<String>,
+    /// Default language of the UI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub babel_default_locale: Option<SupersetLocale>,
+    /// Languages which can be selected in the UI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub languages: Option<Vec<SupersetLocale>>,
+    /// Reference to the Mapbox API key which is used by the map visualizations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mapbox_api_key: Option<SecretKeyRef>,
+    /// Whether forms are protected against cross-site request forgery
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wtf_csrf_enabled: Option<bool>,
+    /// Whether the security headers of Flask-Talisman are set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub talisman_enabled: Option<bool>,
+    /// Settings of the Gunicorn server which runs the webserver. The settings of the role and the
+    /// rolegroup are merged, the rolegroup takes precedence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webserver: Option<WebserverConfig>,
+    /// Feature flags which are merged into `FEATURE_FLAGS`. The flags of the role and the
+    /// rolegroup are merged, the rolegroup takes precedence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feature_flags: Option<BTreeMap<String, bool>>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebserverConfig {
+    /// Number of worker processes, defaults to 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workers: Option<u16>,
+    /// Number of threads per worker, defaults to 20
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threads: Option<u16>,
+    /// Type of the workers, defaults to `gthread`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_class: Option<WorkerClass>,
+    /// Seconds after which a silent worker is restarted, defaults to 60
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u32>,
+    /// Seconds to wait for requests on a keep-alive connection, defaults to 2
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keepalive: Option<u32>,
+    /// Maximum size of the HTTP request line in bytes, defaults to 0 (unlimited)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_request_line: Option<u32>,
+    /// Maximum size of an HTTP request header field in bytes, defaults to 0 (unlimited)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_request_field_size: Option<u32>,
 }
 
-impl SupersetConfig {
-    pub const CREDENTIALS_SECRET_PROPERTY: &'static str = "credentialsSecret";
+impl WebserverConfig {
+    pub const DEFAULT_WORKERS: u16 = 1;
+    pub const DEFAULT_THREADS: u16 = 20;
+    pub const DEFAULT_TIMEOUT: u32 = 60;
+    pub const DEFAULT_KEEPALIVE: u32 = 2;
+    pub const DEFAULT_LIMIT_REQUEST_LINE: u32 = 0;
+    pub const DEFAULT_LIMIT_REQUEST_FIELD_SIZE: u32 = 0;
+
+    /// Returns these settings, overridden by the ones which are set in `other`
+    fn merge(&self, other: &WebserverConfig) -> WebserverConfig {
+        WebserverConfig {
+            workers: other.workers.or(self.workers),
+            threads: other.threads.or(self.threads),
+            worker_class: other.worker_class.or(self.worker_class),
+            timeout: other.timeout.or(self.timeout),
+            keepalive: other.keepalive.or(self.keepalive),
+            limit_request_line: other.limit_request_line.or(self.limit_request_line),
+            limit_request_field_size: other
+                .limit_request_field_size
+                .or(self.limit_request_field_size),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Display, Eq, Hash, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum WorkerClass {
+    Gthread,
+    Gevent,
+}
+
+/// The locales which Superset is translated to
+#[derive(Clone, Copy, Debug, Deserialize, Display, Eq, Hash, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum SupersetLocale {
+    En,
+    Es,
+    It,
+    Fr,
+    Zh,
+    Ja,
+    De,
+    Pt,
+    #[serde(rename = "pt_BR")]
+    #[strum(serialize = "pt_BR")]
+    PtBr,
+    Ru,
+    Ko,
+    Sk,
+    Sl,
+    Nl,
+}
+
+impl SupersetLocale {
+    /// The flag icon and the name of the language in the language menu
+    fn flag_and_name(&self) -> (&'static str, &'static str) {
+        match self {
+            SupersetLocale::En => ("us", "English"),
+            SupersetLocale::Es => ("es", "Spanish"),
+            SupersetLocale::It => ("it", "Italian"),
+            SupersetLocale::Fr => ("fr", "French"),
+            SupersetLocale::Zh => ("cn", "Chinese"),
+            SupersetLocale::Ja => ("jp", "Japanese"),
+            SupersetLocale::De => ("de", "German"),
+            SupersetLocale::Pt => ("pt", "Portuguese"),
+            SupersetLocale::PtBr => ("br", "Brazilian Portuguese"),
+            SupersetLocale::Ru => ("ru", "Russian"),
+            SupersetLocale::Ko => ("kr", "Korean"),
+            SupersetLocale::Sk => ("sk", "Slovak"),
+            SupersetLocale::Sl => ("si", "Slovenian"),
+            SupersetLocale::Nl => ("nl", "Dutch"),
+        }
+    }
+}
+
+/// Feature flags known to Superset, other flags are passed on with a warning
+pub const KNOWN_FEATURE_FLAGS: &[&str] = &[
+    "ALERT_REPORTS",
+    "ALERTS_ATTACH_REPORTS",
+    "ALLOW_FULL_CSV_EXPORT",
+    "CLIENT_CACHE",
+    "DASHBOARD_CACHE",
+    "DASHBOARD_CROSS_FILTERS",
+    "DASHBOARD_FILTERS_EXPERIMENTAL",
+    "DASHBOARD_NATIVE_FILTERS",
+    "DASHBOARD_NATIVE_FILTERS_SET",
+    "DASHBOARD_RBAC",
+    "DISABLE_DATASET_SOURCE_EDIT",
+    "DISABLE_LEGACY_DATASOURCE_EDITOR",
+    "DYNAMIC_PLUGINS",
+    "EMBEDDED_SUPERSET",
+    "ENABLE_EXPLORE_DRAG_AND_DROP",
+    "ENABLE_DND_WITH_CLICK_UX",
+    "ENABLE_JAVASCRIPT_CONTROLS",
+    "ENABLE_REACT_CRUD_VIEWS",
+    "ENABLE_TEMPLATE_PROCESSING",
+    "ENABLE_TEMPLATE_REMOVE_FILTERS",
+    "ESTIMATE_QUERY_COST",
+    "GENERIC_CHART_AXES",
+    "GLOBAL_ASYNC_QUERIES",
+    "KV_STORE",
+    "LISTVIEWS_DEFAULT_CARD_VIEW",
+    "OMNIBAR",
+    "PRESTO_EXPAND_DATA",
+    "REMOVE_SLICE_LEVEL_LABEL_COLORS",
+    "ROW_LEVEL_SECURITY",
+    "SCHEDULED_QUERIES",
+    "SHARE_QUERIES_VIA_KV_STORE",
+    "SQL_VALIDATORS_BY_ENGINE",
+    "SQLLAB_BACKEND_PERSISTENCE",
+    "TAGGING_SYSTEM",
+    "THUMBNAILS",
+    "UX_BETA",
+    "VERSIONED_EXPORT",
+];
+
+/// Returns the feature flags as a Python dict literal
+pub fn feature_flags_dict(feature_flags: &BTreeMap<String, bool>) -> String {
+    let entries = feature_flags
+        .iter()
+        .map(|(flag, enabled)| {
+            format!(
+                "{}: {}",
+                python_string(flag),
+                if *enabled { "True" } else { "False" }
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("{{{}}}", entries.join(", "))
 }
 
 impl Configuration for SupersetConfig {
@@ -141,14 +1125,10 @@ impl Configuration for SupersetConfig {
 
     fn compute_env(
         &self,
-        cluster: &Self::Configurable,
+        _resource: &Self::Configurable,
         _role_name: &str,
     ) -> Result<BTreeMap<String, Option<String>>, ConfigError> {
-        Ok([(
-            Self::CREDENTIALS_SECRET_PROPERTY.to_string(),
-            Some(cluster.spec.credentials_secret.clone()),
-        )]
-        .into())
+        Ok(BTreeMap::new())
     }
 
     fn compute_cli(
@@ -181,14 +1161,273 @@ impl Configuration for SupersetConfig {
 
 #[derive(Clone, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct SupersetClusterStatus {}
+pub struct SupersetClusterStatus {
+    /// Hash of the secret key with which the secrets in the metadata database are encrypted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_key_hash: Option<String>,
+    /// The latest rotation of the secret key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_key_rotation: Option<SecretKeyRotationStatus>,
+    /// Pods in which the `pythonPackages` could not be installed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub python_packages_failures: Vec<PythonPackagesFailure>,
+    /// Feature flags in the config of the rolegroups which this operator does not know, e.g.
+    /// misspelled ones. They are set nevertheless because newer Superset versions add flags.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unknown_feature_flags: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PythonPackagesFailure {
+    pub pod: String,
+    /// The end of the pip output
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretKeyRotationStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<Time>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<Time>,
+    /// Hash of the new secret key
+    pub secret_key_hash: String,
+    /// `PreviousKeyMissing` if no previous secret key is referenced or it does not exist,
+    /// `PreviousKeyMismatch` if the stored secrets are not encrypted with the previous secret key
+    pub condition: SecretKeyRotationCondition,
+}
+
+impl SecretKeyRotationStatus {
+    pub fn new(secret_key_hash: &str) -> Self {
+        Self {
+            started_at: Some(Time(Utc::now())),
+            completed_at: None,
+            secret_key_hash: secret_key_hash.to_string(),
+            condition: SecretKeyRotationCondition::ReEncrypting,
+        }
+    }
+
+    pub fn succeeded(&self) -> Self {
+        let mut new = self.clone();
+        new.completed_at = Some(Time(Utc::now()));
+        new.condition = SecretKeyRotationCondition::Succeeded;
+        new
+    }
+
+    pub fn failed(&self) -> Self {
+        let mut new = self.clone();
+        new.completed_at = Some(Time(Utc::now()));
+        new.condition = SecretKeyRotationCondition::Failed;
+        new
+    }
+
+    /// A rotation which cannot start because the previous key is not available
+    pub fn blocked(secret_key_hash: &str, condition: SecretKeyRotationCondition) -> Self {
+        Self {
+            started_at: None,
+            condition,
+            ..Self::new(secret_key_hash)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, JsonSchema, PartialEq, Serialize)]
+pub enum SecretKeyRotationCondition {
+    ReEncrypting,
+    Succeeded,
+    Failed,
+    // The secret key changed but no previous secret key is referenced or it does not exist
+    PreviousKeyMissing,
+    // The previous secret key is not the one the stored secrets are encrypted with
+    PreviousKeyMismatch,
+}
+
+/// Outcome of the last finished Job which the operator ran for an object
+#[derive(Clone, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobDetails {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<Time>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_time: Option<Time>,
+    /// Message of the failure condition of the Job, e.g. that the backoff limit was reached
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure_message: Option<String>,
+    /// Termination message of the failed container which names the failed step, or the end of its
+    /// log. A succeeded container can report its outcome in the termination message as well.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub termination_message: Option<String>,
+}
+
+/// Limits and cleanup of the Jobs which the operator runs for an object
+#[derive(Clone, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobSettings {
+    /// Number of retries before the Job is marked as failed, defaults to 2
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backoff_limit: Option<i32>,
+    /// Duration in seconds after which a running Job is terminated and marked as failed, defaults
+    /// to 3600
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_deadline_seconds: Option<i64>,
+    /// Duration in seconds after which a finished Job and its pods are deleted, defaults to 86400.
+    /// The outcome of the Job is kept in the status.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_seconds_after_finished: Option<i32>,
+}
+
+impl JobSettings {
+    pub fn backoff_limit(&self) -> i32 {
+        self.backoff_limit.unwrap_or(2)
+    }
+
+    pub fn active_deadline_seconds(&self) -> i64 {
+        self.active_deadline_seconds.unwrap_or(3600)
+    }
+
+    pub fn ttl_seconds_after_finished(&self) -> i32 {
+        self.ttl_seconds_after_finished.unwrap_or(86400)
+    }
+}
 
 impl SupersetCluster {
+    /// The name of the `credentialsSecret`, which defaults to `<cluster name>-credentials` if it
+    /// is generated
+    pub fn credentials_secret_name(&self) -> Option<String> {
+        self.spec.credentials_secret.clone().or_else(|| {
+            self.spec
+                .generate_credentials
+                .as_ref()
+                .map(|_| format!("{}-credentials", self.name()))
+        })
+    }
+
+    /// The Secret keys of all credentials of this cluster
+    pub fn credentials(&self) -> Result<ResolvedCredentials, Error> {
+        ResolvedCredentials::resolve(
+            self.credentials_secret_name().as_deref(),
+            self.spec.credentials.as_ref(),
+            self.spec.metadata_database.as_ref(),
+            self.spec.metadata_database_tls.as_ref(),
+        )
+    }
+
+    /// The config snippets of this cluster, fails if a key is invalid or used more than once
+    pub fn config_snippets(&self) -> Result<&[ConfigSnippet], Error> {
+        validate_config_snippets(&self.spec.config_snippets)
+    }
+
+    /// The name of the Job which re-encrypts the stored secrets with the secret key of the given
+    /// hash
+    pub fn reencrypt_job_name(&self, secret_key_hash: &str) -> String {
+        format!("{}-reencrypt-{secret_key_hash}", self.name())
+    }
+
+    pub fn product_image(&self) -> ProductImage {
+        self.spec.product_image.clone().unwrap_or_default()
+    }
+
+    /// The name of the Secret with the webserver certificate, if one is given
+    pub fn tls_secret_name(&self) -> Option<&str> {
+        match &self.spec.tls {
+            Some(WebserverTls::Secret { name }) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The name of the webserver port, which reflects the protocol
+    pub fn webserver_port_name(&self) -> &'static str {
+        if self.spec.tls.is_some() {
+            HTTPS_PORT
+        } else {
+            HTTP_PORT
+        }
+    }
+
     /// The name of the role-level load-balanced Kubernetes `Service`
     pub fn node_role_service_name(&self) -> Option<String> {
         self.metadata.name.clone()
     }
 
+    /// The feature flags of the role merged with the ones of the given rolegroup
+    pub fn feature_flags(
+        &self,
+        rolegroup: &RoleGroupRef<SupersetCluster>,
+    ) -> BTreeMap<String, bool> {
+        let mut feature_flags = BTreeMap::new();
+        if let Some(nodes) = &self.spec.nodes {
+            let role_flags = nodes.config.config.feature_flags.iter();
+            let rolegroup_flags = nodes
+                .role_groups
+                .get(&rolegroup.role_group)
+                .and_then(|rg| rg.config.config.feature_flags.as_ref());
+            for flags in role_flags.chain(rolegroup_flags) {
+                feature_flags.extend(flags.clone());
+            }
+        }
+        feature_flags
+    }
+
+    /// The port on which the statsd exporter sidecar receives the metrics
+    pub fn statsd_port(&self) -> u16 {
+        self.spec
+            .statsd
+            .as_ref()
+            .and_then(|statsd| statsd.port)
+            .unwrap_or(STATSD_PORT)
+    }
+
+    /// The `STATS_LOGGER` expression which sends the metrics to the configured statsd host or to
+    /// the sidecar. `None` if metrics are not collected at all.
+    pub fn stats_logger(&self) -> Option<String> {
+        let host = match self
+            .spec
+            .statsd
+            .as_ref()
+            .and_then(|statsd| statsd.host.as_ref())
+        {
+            Some(host) => python_string(host),
+            None if self.spec.statsd_exporter_version.is_some() => "'0.0.0.0'".to_string(),
+            None => return None,
+        };
+        Some(format!(
+            "StatsdStatsLogger(host={host}, port={})",
+            self.statsd_port()
+        ))
+    }
+
+    /// The Mapbox API key of the rolegroup, or of the role if the rolegroup does not set one
+    pub fn mapbox_api_key(
+        &self,
+        rolegroup: &RoleGroupRef<SupersetCluster>,
+    ) -> Option<&SecretKeyRef> {
+        let nodes = self.spec.nodes.as_ref()?;
+        nodes
+            .role_groups
+            .get(&rolegroup.role_group)
+            .and_then(|rg| rg.config.config.mapbox_api_key.as_ref())
+            .or(nodes.config.config.mapbox_api_key.as_ref())
+    }
+
+    /// The Gunicorn settings of the role merged with the ones of the given rolegroup
+    pub fn webserver_config(&self, rolegroup: &RoleGroupRef<SupersetCluster>) -> WebserverConfig {
+        let mut webserver_config = WebserverConfig::default();
+        if let Some(nodes) = &self.spec.nodes {
+            let role_config = nodes.config.config.webserver.iter();
+            let rolegroup_config = nodes
+                .role_groups
+                .get(&rolegroup.role_group)
+                .and_then(|rg| rg.config.config.webserver.as_ref());
+            for config in role_config.chain(rolegroup_config) {
+                webserver_config = webserver_config.merge(config);
+            }
+        }
+        webserver_config
+    }
+
     /// Metadata about a node rolegroup
     pub fn node_rolegroup_ref(
         &self,
@@ -211,3 +1450,281 @@ pub struct SupersetClusterRef {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub namespace: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata_database(host: &str, database: &str) -> MetadataDatabase {
+        MetadataDatabase {
+            db_type: DatabaseType::Postgresql,
+            host: host.to_string(),
+            port: None,
+            database: database.to_string(),
+            options: BTreeMap::new(),
+            username: SecretKeyRef::new("metadata-db", "username"),
+            password: SecretKeyRef::new("metadata-db", "password"),
+            tls: None,
+        }
+    }
+
+    #[test]
+    fn uri_location_uses_default_port() {
+        let database = metadata_database("superset-postgresql", "superset");
+        assert_eq!(
+            database.uri_location(),
+            "@superset-postgresql:5432/superset"
+        );
+
+        let database = MetadataDatabase {
+            db_type: DatabaseType::Mysql,
+            ..database
+        };
+        assert_eq!(
+            database.uri_location(),
+            "@superset-postgresql:3306/superset"
+        );
+    }
+
+    #[test]
+    fn uri_location_encodes_reserved_characters() {
+        let mut database = metadata_database("db.example.com", "super@set:/%'\"$");
+        database.port = Some(6432);
+        database
+            .options
+            .insert("application_name".to_string(), "a b&c=d".to_string());
+        assert_eq!(
+            database.uri_location(),
+            "@db.example.com:6432/super%40set%3A%2F%25%27%22%24?application_name=a%20b%26c%3Dd"
+        );
+    }
+
+    #[test]
+    fn uri_location_brackets_ipv6_addresses() {
+        let database = metadata_database("fd00::1", "superset");
+        assert_eq!(database.uri_location(), "@[fd00::1]:5432/superset");
+    }
+
+    #[test]
+    fn uri_location_adds_tls_options() {
+        let mut database = metadata_database("superset-postgresql", "superset");
+        database
+            .options
+            .insert("connect_timeout".to_string(), "10".to_string());
+        database.tls = Some(MetadataDatabaseTls {
+            ssl_mode: Some("verify-full".to_string()),
+            ca: Some(CaCertificate::ConfigMap {
+                name: "metadata-db-ca".to_string(),
+                key: "ca.crt".to_string(),
+            }),
+            client_certificate_secret: None,
+        });
+        assert_eq!(
+            database.uri_location(),
+            "@superset-postgresql:5432/superset?connect_timeout=10&sslmode=verify-full&sslrootcert=%2Fstackable%2Fmetadata-db-tls%2Fca%2Fca.crt"
+        );
+    }
+
+    #[test]
+    fn resolve_takes_the_well_known_keys_of_the_credentials_secret() {
+        let credentials = ResolvedCredentials::resolve(Some("credentials"), None, None, None)
+            .expect("credentials are resolved");
+        assert_eq!(
+            credentials.secret_key,
+            SecretKeyRef::new("credentials", "connections.secretKey")
+        );
+        assert_eq!(
+            credentials.previous_secret_key,
+            Some(SecretKeyRef::new(
+                "credentials",
+                "connections.previousSecretKey"
+            ))
+        );
+        assert_eq!(
+            credentials.metadata_database,
+            MetadataDatabaseConnection::Uri {
+                uri: SecretKeyRef::new("credentials", "connections.sqlalchemyDatabaseUri"),
+                tls: None,
+            }
+        );
+        assert_eq!(
+            credentials.admin_password,
+            SecretKeyRef::new("credentials", "adminUser.password")
+        );
+    }
+
+    #[test]
+    fn resolve_prefers_the_referenced_keys() {
+        let refs = CredentialsRefs {
+            secret_key: Some(SecretKeyRef::new("secret-key", "key")),
+            admin_user: Some(AdminUserRefs {
+                password: Some(SecretKeyRef::new("admin", "password")),
+                ..AdminUserRefs::default()
+            }),
+            ..CredentialsRefs::default()
+        };
+        let credentials =
+            ResolvedCredentials::resolve(Some("credentials"), Some(&refs), None, None)
+                .expect("credentials are resolved");
+        assert_eq!(
+            credentials.secret_key,
+            SecretKeyRef::new("secret-key", "key")
+        );
+        assert_eq!(
+            credentials.admin_password,
+            SecretKeyRef::new("admin", "password")
+        );
+        assert_eq!(
+            credentials.admin_username,
+            SecretKeyRef::new("credentials", "adminUser.username")
+        );
+        assert!(credentials.references_secret("admin"));
+        assert!(!credentials.references_secret("other"));
+    }
+
+    #[test]
+    fn resolve_fails_without_a_source_of_a_credential() {
+        let refs = CredentialsRefs {
+            secret_key: Some(SecretKeyRef::new("secret-key", "key")),
+            ..CredentialsRefs::default()
+        };
+        let error = ResolvedCredentials::resolve(None, Some(&refs), None, None)
+            .expect_err("the URI has no source");
+        assert!(matches!(
+            error,
+            Error::MissingCredential { credential } if credential == "connections.sqlalchemyDatabaseUri"
+        ));
+    }
+
+    #[test]
+    fn resolve_does_not_require_a_uri_for_a_structured_metadata_database() {
+        let admin = |key: &str| Some(SecretKeyRef::new("admin", key));
+        let refs = CredentialsRefs {
+            secret_key: Some(SecretKeyRef::new("secret-key", "key")),
+            admin_user: Some(AdminUserRefs {
+                username: admin("username"),
+                firstname: admin("firstname"),
+                lastname: admin("lastname"),
+                email: admin("email"),
+                password: admin("password"),
+            }),
+            ..CredentialsRefs::default()
+        };
+        let database = metadata_database("superset-postgresql", "superset");
+        let credentials = ResolvedCredentials::resolve(None, Some(&refs), Some(&database), None)
+            .expect("credentials are resolved");
+        assert_eq!(
+            credentials.metadata_database,
+            MetadataDatabaseConnection::Assembled(database)
+        );
+        assert_eq!(credentials.previous_secret_key, None);
+        assert!(credentials.references_secret("metadata-db"));
+    }
+
+    #[test]
+    fn feature_flags_dict_is_a_python_dict_literal() {
+        assert_eq!(feature_flags_dict(&BTreeMap::new()), "{}");
+        let feature_flags = BTreeMap::from([
+            ("DASHBOARD_CACHE".to_string(), false),
+            ("ALERT_REPORTS".to_string(), true),
+        ]);
+        assert_eq!(
+            feature_flags_dict(&feature_flags),
+            "{'ALERT_REPORTS': True, 'DASHBOARD_CACHE': False}"
+        );
+    }
+
+    #[test]
+    fn feature_flags_dict_escapes_the_flag_names() {
+        let feature_flags = BTreeMap::from([("IT'S\\".to_string(), true)]);
+        assert_eq!(feature_flags_dict(&feature_flags), "{'IT\\'S\\\\': True}");
+    }
+
+    #[test]
+    fn languages_dict_uses_the_flags_and_names_of_superset() {
+        assert_eq!(languages_dict(&[]), "{}");
+        assert_eq!(
+            languages_dict(&[SupersetLocale::En, SupersetLocale::PtBr, SupersetLocale::Zh]),
+            "{'en': {'flag': 'us', 'name': 'English'}, 'pt_BR': {'flag': 'br', 'name': 'Brazilian Portuguese'}, 'zh': {'flag': 'cn', 'name': 'Chinese'}}"
+        );
+    }
+
+    #[test]
+    fn config_options_are_rendered_as_python_expressions() {
+        let config = SupersetConfig {
+            csv_export_encoding: Some("utf-8'".to_string()),
+            languages: Some(vec![SupersetLocale::De]),
+            mapbox_api_key: Some(SecretKeyRef::new("mapbox", "apiKey")),
+            ..SupersetConfig::default()
+        };
+        assert_eq!(
+            SupersetConfigOptions::CsvExport.config_type_to_string(&config),
+            Some("{'encoding': 'utf-8\\''}".to_string())
+        );
+        assert_eq!(
+            SupersetConfigOptions::Languages.config_type_to_string(&config),
+            Some("{'de': {'flag': 'de', 'name': 'German'}}".to_string())
+        );
+        assert_eq!(
+            SupersetConfigOptions::MapboxApiKey.config_type_to_string(&config),
+            Some("os.environ.get('MAPBOX_API_KEY')".to_string())
+        );
+        assert_eq!(
+            SupersetConfigOptions::SqlMaxRow.config_type_to_string(&config),
+            None
+        );
+    }
+
+    fn config_snippet(key: &str) -> ConfigSnippet {
+        ConfigSnippet {
+            config_map: "snippets".to_string(),
+            key: key.to_string(),
+            mode: ConfigSnippetMode::Import,
+        }
+    }
+
+    #[test]
+    fn module_name_requires_the_file_name_of_a_python_module() {
+        assert_eq!(
+            config_snippet("security_manager.py").module_name().unwrap(),
+            "security_manager"
+        );
+        for key in [
+            "security-manager.py",
+            "1st.py",
+            ".py",
+            "mutators",
+            "my.mutators.py",
+        ] {
+            assert!(matches!(
+                config_snippet(key).module_name(),
+                Err(Error::InvalidConfigSnippetKey { key: invalid }) if invalid == key
+            ));
+        }
+    }
+
+    #[test]
+    fn config_snippets_must_have_distinct_keys() {
+        let snippets = vec![config_snippet("a.py"), config_snippet("b.py")];
+        assert_eq!(validate_config_snippets(&snippets).unwrap(), &snippets[..]);
+        let snippets = vec![config_snippet("a.py"), config_snippet("a.py")];
+        assert!(matches!(
+            validate_config_snippets(&snippets),
+            Err(Error::DuplicateConfigSnippetKey { key }) if key == "a.py"
+        ));
+    }
+
+    #[test]
+    fn wheelhouse_claim_name_is_camel_case() {
+        let packages: PythonPackages = serde_json::from_str(
+            r#"{"wheelhouse": {"persistentVolumeClaim": {"claimName": "superset-wheelhouse"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            packages.wheelhouse,
+            Some(Wheelhouse::PersistentVolumeClaim {
+                claim_name: "superset-wheelhouse".to_string()
+            })
+        );
+    }
+}
@@ -1,4 +1,8 @@
-use crate::{SupersetCluster, APP_NAME};
+use crate::supersetdbbackup::DatabaseDumpConfig;
+use crate::{
+    ConfigSnippet, CredentialsRefs, JobDetails, JobSettings, MetadataDatabase, MetadataDatabaseTls,
+    ProductImage, PythonPackages, ResolvedCredentials, SecretKeyRef, SupersetCluster, APP_NAME,
+};
 use serde::{Deserialize, Serialize};
 use snafu::{OptionExt, Snafu};
 use stackable_operator::builder::ObjectMetaBuilder;
@@ -7,6 +11,7 @@ use stackable_operator::k8s_openapi::chrono::Utc;
 use stackable_operator::kube::CustomResource;
 use stackable_operator::kube::ResourceExt;
 use stackable_operator::schemars::{self, JsonSchema};
+use strum::EnumIter;
 
 #[derive(Snafu, Debug)]
 #[allow(clippy::enum_variant_names)]
@@ -20,6 +25,10 @@ pub enum Error {
 }
 type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// Blocks the deletion of a SupersetDB with the `Delete` policy until the Superset tables are
+/// dropped
+pub const DROP_SCHEMA_FINALIZER: &str = "superset.stackable.tech/drop-schema";
+
 #[derive(Clone, CustomResource, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
 #[kube(
     group = "superset.stackable.tech",
@@ -37,8 +46,57 @@ type Result<T, E = Error> = std::result::Result<T, E>;
 #[serde(rename_all = "camelCase")]
 pub struct SupersetDBSpec {
     pub superset_version: String,
-    pub credentials_secret: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials_secret: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<CredentialsRefs>,
     pub load_examples: bool,
+    /// If set, the metadata database is dumped before its schema is migrated to a new Superset
+    /// version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_before_migration: Option<DatabaseDumpConfig>,
+    /// Whether the Superset tables are dropped when the SupersetDB is deleted, defaults to `Retain`.
+    /// `Retain` keeps the schema so that it can be reused, `Delete` drops the Superset tables
+    /// before the SupersetDB is removed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deletion_policy: Option<DeletionPolicy>,
+    /// If `true`, an existing schema is adopted as it is: the admin user is not created and the
+    /// examples are not loaded if the Superset tables already exist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adopt_existing_schema: Option<bool>,
+    /// Retries, deadline and cleanup of the initialization, migration and drop Jobs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<JobSettings>,
+    /// Connection details from which the SQLAlchemy URI is assembled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_database: Option<MetadataDatabase>,
+    /// TLS settings of a metadata database whose URI is taken from the credentials
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_database_tls: Option<MetadataDatabaseTls>,
+    /// The image of the Jobs, see the SupersetCluster
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product_image: Option<ProductImage>,
+    /// Additional Python packages which are installed before the Jobs run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub python_packages: Option<PythonPackages>,
+    /// Python modules which are added to the `superset_config.py` of the Jobs, see the
+    /// SupersetCluster
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub config_snippets: Vec<ConfigSnippet>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, JsonSchema, PartialEq, Serialize)]
+pub enum DeletionPolicy {
+    // The schema survives the deletion of the SupersetDB and can be reused
+    Retain,
+    // The Superset tables are dropped before the SupersetDB is removed
+    Delete,
+}
+
+impl Default for DeletionPolicy {
+    fn default() -> Self {
+        DeletionPolicy::Retain
+    }
 }
 
 impl SupersetDB {
@@ -59,15 +117,79 @@ impl SupersetDB {
                 .build(),
             spec: SupersetDBSpec {
                 superset_version: version.to_string(),
-                credentials_secret: superset.spec.credentials_secret.clone(),
+                credentials_secret: superset.credentials_secret_name(),
+                credentials: superset.spec.credentials.clone(),
                 load_examples: superset.spec.load_examples_on_init.unwrap_or_default(),
+                backup_before_migration: superset.spec.backup_before_migration.clone(),
+                deletion_policy: superset.spec.database_deletion_policy,
+                adopt_existing_schema: superset.spec.adopt_existing_schema,
+                jobs: superset.spec.database_jobs.clone(),
+                metadata_database: superset.spec.metadata_database.clone(),
+                metadata_database_tls: superset.spec.metadata_database_tls.clone(),
+                product_image: superset.spec.product_image.clone(),
+                python_packages: superset.spec.python_packages.clone(),
+                config_snippets: superset.spec.config_snippets.clone(),
             },
             status: None,
         })
     }
 
-    pub fn job_name(&self) -> String {
-        self.name()
+    /// Makes the Jobs use the given secret key instead of the one of the cluster, e.g. the previous
+    /// key while the stored secrets are re-encrypted
+    pub fn with_secret_key(mut self, secret_key: SecretKeyRef) -> Self {
+        self.spec
+            .credentials
+            .get_or_insert_with(CredentialsRefs::default)
+            .secret_key = Some(secret_key);
+        self
+    }
+
+    /// The Secret keys of all credentials of this database
+    pub fn credentials(&self) -> Result<ResolvedCredentials, crate::Error> {
+        ResolvedCredentials::resolve(
+            self.spec.credentials_secret.as_deref(),
+            self.spec.credentials.as_ref(),
+            self.spec.metadata_database.as_ref(),
+            self.spec.metadata_database_tls.as_ref(),
+        )
+    }
+
+    /// The config snippets of the Jobs, fails if a key is invalid or used more than once
+    pub fn config_snippets(&self) -> Result<&[ConfigSnippet], crate::Error> {
+        crate::validate_config_snippets(&self.spec.config_snippets)
+    }
+
+    pub fn product_image(&self) -> ProductImage {
+        self.spec.product_image.clone().unwrap_or_default()
+    }
+
+    /// The Superset image of the Jobs
+    pub fn superset_image(&self) -> String {
+        self.product_image()
+            .superset_image(&self.spec.superset_version)
+    }
+
+    /// The name of the Job which runs the given initialization phase
+    pub fn phase_job_name(&self, phase: InitPhase) -> String {
+        format!("{}-{}", self.name(), phase.job_suffix())
+    }
+
+    pub fn drop_job_name(&self) -> String {
+        format!("{}-drop", self.name())
+    }
+
+    /// The ConfigMap with the generated files of the initialization and drop Jobs
+    pub fn job_config_map_name(&self) -> String {
+        format!("{}-job-config", self.name())
+    }
+
+    /// Returns true if the schema was set up with a different Superset version than the desired
+    /// one and therefore has to be migrated
+    pub fn needs_migration(&self) -> bool {
+        self.status
+            .as_ref()
+            .and_then(|status| status.superset_version.as_deref())
+            .map_or(false, |version| version != self.spec.superset_version)
     }
 }
 
@@ -76,25 +198,57 @@ impl SupersetDB {
 pub struct SupersetDBStatus {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub started_at: Option<Time>,
+    /// The Superset version the schema was last initialized or migrated with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub superset_version: Option<String>,
     pub condition: SupersetDBStatusCondition,
+    /// Whether the schema existed before the last initialization and was adopted. It is recorded
+    /// when the schema migration has finished and is unset if that could not be determined, in
+    /// which case the admin user and the examples are not added either.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub existing_schema_adopted: Option<bool>,
+    /// The phases of the last initialization in the order in which they were run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub phases: Vec<InitPhaseStatus>,
 }
 
 impl SupersetDBStatus {
     pub fn new() -> Self {
         Self {
             started_at: Some(Time(Utc::now())),
+            superset_version: None,
             condition: SupersetDBStatusCondition::Pending,
+            existing_schema_adopted: None,
+            phases: Vec::new(),
+        }
+    }
+
+    /// Starts over with a pending status but keeps the version of the existing schema so that it
+    /// is migrated
+    pub fn migrating(&self) -> Self {
+        Self {
+            superset_version: self.superset_version.clone(),
+            ..Self::new()
         }
     }
 
     pub fn initializing(&self) -> Self {
         let mut new = self.clone();
         new.condition = SupersetDBStatusCondition::Initializing;
+        new.existing_schema_adopted = None;
+        new.phases = Vec::new();
+        new
+    }
+
+    pub fn existing_schema_adopted(&self, adopted: Option<bool>) -> Self {
+        let mut new = self.clone();
+        new.existing_schema_adopted = adopted;
         new
     }
 
-    pub fn ready(&self) -> Self {
+    pub fn ready(&self, superset_version: &str) -> Self {
         let mut new = self.clone();
+        new.superset_version = Some(superset_version.to_string());
         new.condition = SupersetDBStatusCondition::Ready;
         new
     }
@@ -104,6 +258,52 @@ impl SupersetDBStatus {
         new.condition = SupersetDBStatusCondition::Failed;
         new
     }
+
+    pub fn start_phase(&self, phase: InitPhase) -> Self {
+        let mut new = self.clone();
+        new.phases.push(InitPhaseStatus {
+            phase,
+            condition: InitPhaseCondition::Running,
+            job: None,
+        });
+        new
+    }
+
+    pub fn skip_phase(&self, phase: InitPhase) -> Self {
+        let mut new = self.clone();
+        new.phases.push(InitPhaseStatus {
+            phase,
+            condition: InitPhaseCondition::Skipped,
+            job: None,
+        });
+        new
+    }
+
+    pub fn finish_phase(
+        &self,
+        phase: InitPhase,
+        condition: InitPhaseCondition,
+        job: JobDetails,
+    ) -> Self {
+        let mut new = self.clone();
+        for phase_status in new.phases.iter_mut().filter(|status| status.phase == phase) {
+            phase_status.condition = condition;
+            phase_status.job = Some(job.clone());
+        }
+        new
+    }
+
+    /// The phase whose Job is currently running
+    pub fn running_phase(&self) -> Option<InitPhase> {
+        self.phases
+            .iter()
+            .find(|status| status.condition == InitPhaseCondition::Running)
+            .map(|status| status.phase)
+    }
+
+    pub fn phase(&self, phase: InitPhase) -> Option<&InitPhaseStatus> {
+        self.phases.iter().find(|status| status.phase == phase)
+    }
 }
 
 impl Default for SupersetDBStatus {
@@ -119,3 +319,47 @@ pub enum SupersetDBStatusCondition {
     Ready,
     Failed,
 }
+
+/// The phases of the initialization, each of them is run by its own Job
+#[derive(Clone, Copy, Debug, Deserialize, EnumIter, Eq, Hash, JsonSchema, PartialEq, Serialize)]
+pub enum InitPhase {
+    // `superset db upgrade`
+    SchemaMigration,
+    // `superset init` which sets up the default roles and permissions
+    RolesAndPermissions,
+    // `superset fab create-admin`
+    AdminCreation,
+    // `superset load_examples`, which runs after the database is `Ready`
+    Examples,
+}
+
+impl InitPhase {
+    pub fn job_suffix(&self) -> &'static str {
+        match self {
+            InitPhase::SchemaMigration => "migrate",
+            InitPhase::RolesAndPermissions => "init",
+            InitPhase::AdminCreation => "create-admin",
+            InitPhase::Examples => "load-examples",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitPhaseStatus {
+    /// `SchemaMigration` runs `superset db upgrade`, `RolesAndPermissions` `superset init`,
+    /// `AdminCreation` `superset fab create-admin` and `Examples` `superset load_examples` after
+    /// the database is `Ready`
+    pub phase: InitPhase,
+    pub condition: InitPhaseCondition,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<JobDetails>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, JsonSchema, PartialEq, Serialize)]
+pub enum InitPhaseCondition {
+    Running,
+    Succeeded,
+    Failed,
+    Skipped,
+}
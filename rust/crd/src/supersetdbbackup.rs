@@ -0,0 +1,159 @@
+use crate::supersetdb::SupersetDB;
+use crate::{DatabaseType, JobSettings, ProductImage};
+use serde::{Deserialize, Serialize};
+use stackable_operator::k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use stackable_operator::k8s_openapi::chrono::Utc;
+use stackable_operator::kube::CustomResource;
+use stackable_operator::kube::ResourceExt;
+use stackable_operator::schemars::{self, JsonSchema};
+
+/// The mount path of the volume the dumps are written to and read from
+pub const BACKUP_DIR: &str = "/backup";
+
+#[derive(Clone, CustomResource, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[kube(
+    group = "superset.stackable.tech",
+    version = "v1alpha1",
+    kind = "SupersetDBBackup",
+    plural = "supersetdbbackups",
+    status = "SupersetDBBackupStatus",
+    namespaced,
+    crates(
+        kube_core = "stackable_operator::kube::core",
+        k8s_openapi = "stackable_operator::k8s_openapi",
+        schemars = "stackable_operator::schemars"
+    )
+)]
+#[serde(rename_all = "camelCase")]
+pub struct SupersetDBBackupSpec {
+    /// Name of the SupersetDB in the same namespace whose metadata database is dumped
+    pub superset_db: String,
+    #[serde(flatten)]
+    pub dump: DatabaseDumpConfig,
+    /// Retries, deadline and cleanup of the backup Job
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<JobSettings>,
+}
+
+/// Where and how a dump of the metadata database is stored
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseDumpConfig {
+    /// Type of the metadata database, selects between `pg_dump` and `mysqldump`
+    pub db_type: DatabaseType,
+    /// Name of the PersistentVolumeClaim the dump is written to
+    pub claim_name: String,
+    /// Image containing the database client tools, defaults to the official image of `dbType`
+    /// in the `repo` of the `productImage`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+}
+
+impl DatabaseDumpConfig {
+    pub fn client_image(&self, product_image: &ProductImage) -> String {
+        self.image
+            .clone()
+            .unwrap_or_else(|| product_image.database_client_image(self.db_type))
+    }
+}
+
+impl SupersetDBBackup {
+    /// Returns a backup of the given SupersetDB which is taken before its schema is migrated to
+    /// the version in its spec.
+    ///
+    /// Like the SupersetDB itself, the backup is deliberately not owned so that it is still
+    /// available after the SupersetDB is deleted.
+    ///
+    /// The name contains the generation of the SupersetDB, so that a backup of an earlier
+    /// migration to the same version is not mistaken for the one of the current migration. Its
+    /// Job has the settings of the SupersetDB Jobs.
+    pub fn pre_migration(superset_db: &SupersetDB, dump: &DatabaseDumpConfig) -> Self {
+        let mut backup = Self::new(
+            &format!(
+                "{}-pre-{}-{}",
+                superset_db.name(),
+                superset_db.spec.superset_version.replace('.', "-"),
+                superset_db.metadata.generation.unwrap_or_default()
+            ),
+            SupersetDBBackupSpec {
+                superset_db: superset_db.name(),
+                dump: dump.clone(),
+                job: superset_db.spec.jobs.clone(),
+            },
+        );
+        backup.metadata.namespace = superset_db.namespace();
+        backup
+    }
+
+    pub fn job_name(&self) -> String {
+        format!("{}-backup", self.name())
+    }
+
+    /// The path of the dump file within the backup volume
+    pub fn dump_file(&self) -> String {
+        format!(
+            "{BACKUP_DIR}/{}.{}",
+            self.name(),
+            self.spec.dump.db_type.dump_file_extension()
+        )
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SupersetDBBackupStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<Time>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<Time>,
+    /// The Superset version of the schema contained in the dump
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub superset_version: Option<String>,
+    pub condition: SupersetDBBackupStatusCondition,
+}
+
+impl SupersetDBBackupStatus {
+    pub fn new() -> Self {
+        Self {
+            started_at: Some(Time(Utc::now())),
+            completed_at: None,
+            superset_version: None,
+            condition: SupersetDBBackupStatusCondition::Pending,
+        }
+    }
+
+    pub fn running(&self, superset_version: &str) -> Self {
+        let mut new = self.clone();
+        new.superset_version = Some(superset_version.to_string());
+        new.condition = SupersetDBBackupStatusCondition::Running;
+        new
+    }
+
+    pub fn succeeded(&self) -> Self {
+        let mut new = self.clone();
+        new.completed_at = Some(Time(Utc::now()));
+        new.condition = SupersetDBBackupStatusCondition::Succeeded;
+        new
+    }
+
+    pub fn failed(&self) -> Self {
+        let mut new = self.clone();
+        new.completed_at = Some(Time(Utc::now()));
+        new.condition = SupersetDBBackupStatusCondition::Failed;
+        new
+    }
+}
+
+impl Default for SupersetDBBackupStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, JsonSchema, PartialEq, Serialize)]
+pub enum SupersetDBBackupStatusCondition {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+}
@@ -0,0 +1,90 @@
+use crate::JobSettings;
+use serde::{Deserialize, Serialize};
+use stackable_operator::k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use stackable_operator::k8s_openapi::chrono::Utc;
+use stackable_operator::kube::CustomResource;
+use stackable_operator::kube::ResourceExt;
+use stackable_operator::schemars::{self, JsonSchema};
+
+#[derive(Clone, CustomResource, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[kube(
+    group = "superset.stackable.tech",
+    version = "v1alpha1",
+    kind = "SupersetDBRestore",
+    plural = "supersetdbrestores",
+    status = "SupersetDBRestoreStatus",
+    namespaced,
+    crates(
+        kube_core = "stackable_operator::kube::core",
+        k8s_openapi = "stackable_operator::k8s_openapi",
+        schemars = "stackable_operator::schemars"
+    )
+)]
+#[serde(rename_all = "camelCase")]
+pub struct SupersetDBRestoreSpec {
+    /// Name of the succeeded SupersetDBBackup in the same namespace which is restored
+    pub backup: String,
+    /// Retries, deadline and cleanup of the restore Job
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<JobSettings>,
+}
+
+impl SupersetDBRestore {
+    pub fn job_name(&self) -> String {
+        format!("{}-restore", self.name())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SupersetDBRestoreStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<Time>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<Time>,
+    pub condition: SupersetDBRestoreStatusCondition,
+}
+
+impl SupersetDBRestoreStatus {
+    pub fn new() -> Self {
+        Self {
+            started_at: Some(Time(Utc::now())),
+            completed_at: None,
+            condition: SupersetDBRestoreStatusCondition::Pending,
+        }
+    }
+
+    pub fn restoring(&self) -> Self {
+        let mut new = self.clone();
+        new.condition = SupersetDBRestoreStatusCondition::Restoring;
+        new
+    }
+
+    pub fn succeeded(&self) -> Self {
+        let mut new = self.clone();
+        new.completed_at = Some(Time(Utc::now()));
+        new.condition = SupersetDBRestoreStatusCondition::Succeeded;
+        new
+    }
+
+    pub fn failed(&self) -> Self {
+        let mut new = self.clone();
+        new.completed_at = Some(Time(Utc::now()));
+        new.condition = SupersetDBRestoreStatusCondition::Failed;
+        new
+    }
+}
+
+impl Default for SupersetDBRestoreStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, JsonSchema, PartialEq, Serialize)]
+pub enum SupersetDBRestoreStatusCondition {
+    Pending,
+    Restoring,
+    Succeeded,
+    Failed,
+}
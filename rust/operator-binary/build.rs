@@ -1,6 +1,8 @@
 use stackable_operator::crd::CustomResourceExt;
 use stackable_superset_crd::druidconnection::DruidConnection;
 use stackable_superset_crd::supersetdb::SupersetDB;
+use stackable_superset_crd::supersetdbbackup::SupersetDBBackup;
+use stackable_superset_crd::supersetdbrestore::SupersetDBRestore;
 use stackable_superset_crd::SupersetCluster;
 
 fn main() {
@@ -9,4 +11,6 @@ fn main() {
     SupersetCluster::write_yaml_schema("../../deploy/crd/supersetcluster.crd.yaml").unwrap();
     SupersetDB::write_yaml_schema("../../deploy/crd/supersetdb.crd.yaml").unwrap();
     DruidConnection::write_yaml_schema("../../deploy/crd/druidconnection.crd.yaml").unwrap();
+    SupersetDBBackup::write_yaml_schema("../../deploy/crd/supersetdbbackup.crd.yaml").unwrap();
+    SupersetDBRestore::write_yaml_schema("../../deploy/crd/supersetdbrestore.crd.yaml").unwrap();
 }
@@ -1,17 +1,25 @@
-use crate::util::{get_job_state, JobState};
+use crate::metrics::{Metrics, DRUID_CONNECTION_CONTROLLER};
+use crate::util::{
+    add_image_pull_settings, add_job_config_volume, add_metadata_database_tls, add_python_packages,
+    add_superset_config_env, get_job_state, job_details, job_failure_note, job_spec, publish_event,
+    superset_job_config, with_failure_summary, JobState,
+};
 
-use snafu::{OptionExt, ResultExt, Snafu};
+use serde_json::json;
+use snafu::{ResultExt, Snafu};
 use stackable_operator::{
     builder::{ContainerBuilder, ObjectMetaBuilder},
     client::Client,
     k8s_openapi::api::{
-        batch::v1::{Job, JobSpec},
+        batch::v1::Job,
         core::v1::{ConfigMap, PodSpec, PodTemplateSpec},
     },
     kube::{
+        api::Api,
         core::DynamicObject,
         runtime::{
             controller::{Action, Context},
+            events::EventType,
             reflector::ObjectRef,
         },
         ResourceExt,
@@ -19,7 +27,10 @@ use stackable_operator::{
     logging::controller::ReconcilerError,
 };
 use stackable_superset_crd::{
-    druidconnection::{DruidConnection, DruidConnectionStatus, DruidConnectionStatusCondition},
+    druidconnection::{
+        DruidConnection, DruidConnectionPendingReason, DruidConnectionStatus,
+        DruidConnectionStatusCondition,
+    },
     supersetdb::{SupersetDB, SupersetDBStatusCondition},
     PYTHONPATH, SUPERSET_CONFIG_FILENAME,
 };
@@ -28,8 +39,15 @@ use strum::{EnumDiscriminants, IntoStaticStr};
 
 const FIELD_MANAGER_SCOPE: &str = "supersetcluster";
 
+/// The document in the ConfigMap of the import Job which is imported into Superset
+const IMPORT_FILENAME: &str = "druids.yaml";
+
+/// Interval in which the dependencies of a pending connection are checked again
+const PENDING_REQUEUE_INTERVAL: Duration = Duration::from_secs(30);
+
 pub struct Ctx {
     pub client: stackable_operator::client::Client,
+    pub metrics: Arc<Metrics>,
 }
 
 #[derive(Snafu, Debug, EnumDiscriminants)]
@@ -44,29 +62,40 @@ pub enum Error {
     ApplyStatus {
         source: stackable_operator::error::Error,
     },
+    #[snafu(display("failed to serialize the document to import"))]
+    SerializeImportDocument { source: serde_yaml::Error },
+    #[snafu(display("failed to apply the ConfigMap of the import Job"))]
+    ApplyImportConfigMap {
+        source: stackable_operator::error::Error,
+    },
     #[snafu(display("object is missing metadata to build owner reference"))]
     ObjectMissingMetadataForOwnerRef {
         source: stackable_operator::error::Error,
     },
     #[snafu(display("failed to get Druid connection string from config map {config_map}"))]
     GetDruidConnStringConfigMap {
-        source: stackable_operator::error::Error,
+        source: stackable_operator::kube::Error,
         config_map: ObjectRef<ConfigMap>,
     },
-    #[snafu(display("failed to get Druid connection string from config map"))]
-    MissingDruidConnString,
     #[snafu(display("druid connection state is 'importing' but failed to find job {import_job}"))]
     GetImportJob {
-        source: stackable_operator::error::Error,
+        source: stackable_operator::kube::Error,
         import_job: ObjectRef<Job>,
     },
-    #[snafu(display("failed to check if druid discovery map exists"))]
-    DruidDiscoveryCheck {
-        source: stackable_operator::error::Error,
-    },
-    #[snafu(display("failed to retrieve superset db"))]
+    #[snafu(display("failed to retrieve superset db {superset_db}"))]
     SupersetDBRetrieval {
-        source: stackable_operator::error::Error,
+        source: stackable_operator::kube::Error,
+        superset_db: ObjectRef<SupersetDB>,
+    },
+    #[snafu(display("failed to collect the details of the finished Job"))]
+    JobDetails { source: crate::util::Error },
+    #[snafu(display("invalid credentials"))]
+    InvalidCredentials {
+        source: stackable_superset_crd::Error,
+    },
+    #[snafu(display("invalid config snippets of the SupersetDB"))]
+    InvalidConfigSnippets {
+        source: stackable_superset_crd::Error,
     },
 }
 
@@ -81,14 +110,17 @@ impl ReconcilerError for Error {
         match self {
             Error::ApplyJob { .. } => None,
             Error::ApplyStatus { .. } => None,
+            Error::SerializeImportDocument { .. } => None,
+            Error::ApplyImportConfigMap { .. } => None,
             Error::ObjectMissingMetadataForOwnerRef { .. } => None,
             Error::GetDruidConnStringConfigMap { config_map, .. } => {
                 Some(config_map.clone().erase())
             }
-            Error::MissingDruidConnString => None,
             Error::GetImportJob { import_job, .. } => Some(import_job.clone().erase()),
-            Error::DruidDiscoveryCheck { .. } => None,
-            Error::SupersetDBRetrieval { .. } => None,
+            Error::SupersetDBRetrieval { superset_db, .. } => Some(superset_db.clone().erase()),
+            Error::JobDetails { .. } => None,
+            Error::InvalidCredentials { .. } => None,
+            Error::InvalidConfigSnippets { .. } => None,
         }
     }
 }
@@ -98,60 +130,63 @@ pub async fn reconcile_druid_connection(
     ctx: Context<Ctx>,
 ) -> Result<Action> {
     tracing::info!("Starting reconciling DruidConnections");
+    let _reconcile_guard = ctx
+        .get_ref()
+        .metrics
+        .reconcile_started(DRUID_CONNECTION_CONTROLLER, &*druid_connection);
 
     let client = &ctx.get_ref().client;
 
     if let Some(ref s) = druid_connection.status {
         match s.condition {
             DruidConnectionStatusCondition::Pending => {
-                // Is the superset DB object there, and is its status "Ready"?
-                let mut superset_db_ready = false;
-                if let Some(status) = client
-                    .get::<SupersetDB>(
-                        &druid_connection.spec.superset.name,
-                        Some(&druid_connection.spec.superset.namespace),
-                    )
-                    .await
-                    .context(SupersetDBRetrievalSnafu)?
-                    .status
-                {
-                    superset_db_ready = status.condition == SupersetDBStatusCondition::Ready;
-                }
-                // Is the referenced druid discovery configmap there?
-                let druid_discovery_cm_exists = client
-                    .exists::<ConfigMap>(
-                        &druid_connection.spec.druid.name,
-                        Some(&druid_connection.spec.druid.namespace),
-                    )
-                    .await
-                    .context(DruidDiscoveryCheckSnafu)?;
-
-                if superset_db_ready && druid_discovery_cm_exists {
-                    let superset_db = client
-                        .get::<SupersetDB>(
-                            &druid_connection.spec.superset.name,
-                            Some(&druid_connection.spec.superset.namespace),
+                match import_prerequisites(client, &druid_connection).await? {
+                    ImportPrerequisites::Available {
+                        superset_db,
+                        sqlalchemy_uri,
+                    } => {
+                        apply_import_config_map(
+                            client,
+                            &druid_connection,
+                            &superset_db,
+                            &sqlalchemy_uri,
+                        )
+                        .await?;
+                        let job = build_import_job(&druid_connection, &superset_db)?;
+                        client
+                            .apply_patch(FIELD_MANAGER_SCOPE, &job, &job)
+                            .await
+                            .context(ApplyJobSnafu)?;
+                        publish_event(
+                            client,
+                            &*druid_connection,
+                            EventType::Normal,
+                            "CreatedJob",
+                            format!("Created the import Job {}", druid_connection.job_name()),
                         )
-                        .await
-                        .context(SupersetDBRetrievalSnafu)?;
-                    // Everything is there, retrieve all necessary info and start the job
-                    let sqlalchemy_str = get_sqlalchemy_uri_for_druid_cluster(
-                        &druid_connection.spec.druid.name,
-                        &druid_connection.spec.druid.namespace,
-                        client,
-                    )
-                    .await?;
-                    let job =
-                        build_import_job(&druid_connection, &superset_db, &sqlalchemy_str).await?;
-                    client
-                        .apply_patch(FIELD_MANAGER_SCOPE, &job, &job)
-                        .await
-                        .context(ApplyJobSnafu)?;
-                    // The job is started, update status to reflect new state
-                    client
-                        .apply_patch_status(FIELD_MANAGER_SCOPE, &*druid_connection, &s.importing())
-                        .await
-                        .context(ApplyStatusSnafu)?;
+                        .await;
+                        // The job is started, update status to reflect new state
+                        apply_status(client, &druid_connection, &s.importing()).await?;
+                    }
+                    ImportPrerequisites::Blocked { reason, message } => {
+                        if s.reason != Some(reason) {
+                            publish_event(
+                                client,
+                                &*druid_connection,
+                                EventType::Warning,
+                                &format!("{reason:?}"),
+                                message.clone(),
+                            )
+                            .await;
+                        }
+                        if s.reason != Some(reason) || s.message.as_ref() != Some(&message) {
+                            apply_status(client, &druid_connection, &s.pending(reason, message))
+                                .await?;
+                        }
+                        // The Druid discovery ConfigMap can be in another namespace where it is not
+                        // watched, so the dependencies are checked again periodically
+                        return Ok(Action::requeue(PENDING_REQUEUE_INTERVAL));
+                    }
                 }
             }
             DruidConnectionStatusCondition::Importing => {
@@ -159,25 +194,53 @@ pub async fn reconcile_druid_connection(
                     .namespace()
                     .unwrap_or_else(|| "default".to_string());
                 let job_name = druid_connection.job_name();
-                let job =
-                    client
-                        .get::<Job>(&job_name, Some(&ns))
-                        .await
-                        .context(GetImportJobSnafu {
+                let job = match Api::<Job>::namespaced(client.as_kube_client(), &ns)
+                    .get(&job_name)
+                    .await
+                {
+                    Ok(job) => job,
+                    Err(stackable_operator::kube::Error::Api(response)) if response.code == 404 => {
+                        // The Job was deleted before its outcome was recorded, e.g. because it
+                        // was garbage-collected while the operator was not running. The import
+                        // is started over.
+                        publish_event(
+                            client,
+                            &*druid_connection,
+                            EventType::Warning,
+                            "JobNotFound",
+                            format!(
+                                "The import Job {job_name} was deleted before it was observed as \
+                                 finished, the import is started again"
+                            ),
+                        )
+                        .await;
+                        apply_status(client, &druid_connection, &DruidConnectionStatus::new())
+                            .await?;
+                        return Ok(Action::await_change());
+                    }
+                    Err(source) => {
+                        return Err(Error::GetImportJob {
+                            source,
                             import_job: ObjectRef::<Job>::new(&job_name).within(&ns),
-                        })?;
+                        })
+                    }
+                };
 
-                let new_status = match get_job_state(&job) {
+                let job_state = get_job_state(&job);
+                ctx.get_ref().metrics.job_finished(
+                    DRUID_CONNECTION_CONTROLLER,
+                    "druid-import",
+                    &job_state,
+                );
+                let new_status = match job_state {
                     JobState::Failed => Some(s.failed()),
                     JobState::Complete => Some(s.ready()),
                     JobState::InProgress => None,
                 };
 
                 if let Some(ns) = new_status {
-                    client
-                        .apply_patch_status(FIELD_MANAGER_SCOPE, &*druid_connection, &ns)
-                        .await
-                        .context(ApplyStatusSnafu)?;
+                    let job_details = job_details(client, &job).await.context(JobDetailsSnafu)?;
+                    apply_status(client, &druid_connection, &ns.with_job(job_details)).await?;
                 }
             }
             DruidConnectionStatusCondition::Ready => (),
@@ -185,87 +248,266 @@ pub async fn reconcile_druid_connection(
         }
     } else {
         // Status not set yet, initialize
-        client
-            .apply_patch_status(
-                FIELD_MANAGER_SCOPE,
-                &*druid_connection,
-                &DruidConnectionStatus::new(),
-            )
-            .await
-            .context(ApplyStatusSnafu)?;
+        apply_status(client, &druid_connection, &DruidConnectionStatus::new()).await?;
     }
 
     Ok(Action::await_change())
 }
 
-/// Takes a druid cluster name and namespace and returns the SQLAlchemy connect string
-async fn get_sqlalchemy_uri_for_druid_cluster(
-    cluster_name: &str,
-    namespace: &str,
+/// Patches the status and publishes an event if the condition has changed
+async fn apply_status(
     client: &Client,
-) -> Result<String> {
+    druid_connection: &DruidConnection,
+    status: &DruidConnectionStatus,
+) -> Result<()> {
     client
-        .get::<ConfigMap>(cluster_name, Some(namespace))
+        .apply_patch_status(FIELD_MANAGER_SCOPE, druid_connection, status)
+        .await
+        .context(ApplyStatusSnafu)?;
+
+    let previous_condition = druid_connection
+        .status
+        .as_ref()
+        .map(|status| status.condition);
+    if previous_condition != Some(status.condition) {
+        let type_ = if status.condition == DruidConnectionStatusCondition::Failed {
+            EventType::Warning
+        } else {
+            EventType::Normal
+        };
+        let note = match previous_condition {
+            Some(previous_condition) => format!(
+                "The condition changed from {previous_condition:?} to {:?}",
+                status.condition
+            ),
+            None => format!("The condition is {:?}", status.condition),
+        };
+        let note = match status
+            .job
+            .as_ref()
+            .filter(|_| status.condition == DruidConnectionStatusCondition::Failed)
+        {
+            Some(job) => job_failure_note(note, job),
+            None => note,
+        };
+        publish_event(
+            client,
+            druid_connection,
+            type_,
+            &format!("{:?}", status.condition),
+            note,
+        )
+        .await;
+    }
+    Ok(())
+}
+
+enum ImportPrerequisites {
+    Available {
+        superset_db: SupersetDB,
+        sqlalchemy_uri: String,
+    },
+    Blocked {
+        reason: DruidConnectionPendingReason,
+        message: String,
+    },
+}
+
+/// Retrieves the Ready SupersetDB and the SQLAlchemy URI of the Druid cluster, or the dependency
+/// which blocks the import
+async fn import_prerequisites(
+    client: &Client,
+    druid_connection: &DruidConnection,
+) -> Result<ImportPrerequisites> {
+    let superset = &druid_connection.spec.superset;
+    let superset_db =
+        match Api::<SupersetDB>::namespaced(client.as_kube_client(), &superset.namespace)
+            .get(&superset.name)
+            .await
+        {
+            Ok(superset_db) => superset_db,
+            Err(stackable_operator::kube::Error::Api(response)) if response.code == 404 => {
+                return Ok(ImportPrerequisites::Blocked {
+                    reason: DruidConnectionPendingReason::SupersetDBNotFound,
+                    message: format!(
+                        "The SupersetDB {}/{} does not exist",
+                        superset.namespace, superset.name
+                    ),
+                });
+            }
+            Err(source) => {
+                return Err(Error::SupersetDBRetrieval {
+                    source,
+                    superset_db: ObjectRef::new(&superset.name).within(&superset.namespace),
+                })
+            }
+        };
+    let superset_db_condition = superset_db.status.as_ref().map(|status| status.condition);
+    if superset_db_condition != Some(SupersetDBStatusCondition::Ready) {
+        return Ok(ImportPrerequisites::Blocked {
+            reason: DruidConnectionPendingReason::SupersetDBNotReady,
+            message: format!(
+                "The SupersetDB {}/{} is not ready but {:?}",
+                superset.namespace,
+                superset.name,
+                superset_db_condition.unwrap_or(SupersetDBStatusCondition::Pending)
+            ),
+        });
+    }
+
+    let druid = &druid_connection.spec.druid;
+    let config_map = match Api::<ConfigMap>::namespaced(client.as_kube_client(), &druid.namespace)
+        .get(&druid.name)
         .await
-        .context(GetDruidConnStringConfigMapSnafu {
-            config_map: ObjectRef::<ConfigMap>::new(cluster_name).within(namespace),
-        })?
+    {
+        Ok(config_map) => config_map,
+        Err(stackable_operator::kube::Error::Api(response)) if response.code == 404 => {
+            return Ok(ImportPrerequisites::Blocked {
+                reason: DruidConnectionPendingReason::DruidConfigMapNotFound,
+                message: format!(
+                    "The Druid discovery ConfigMap {}/{} does not exist",
+                    druid.namespace, druid.name
+                ),
+            });
+        }
+        Err(source) => {
+            return Err(Error::GetDruidConnStringConfigMap {
+                source,
+                config_map: ObjectRef::new(&druid.name).within(&druid.namespace),
+            })
+        }
+    };
+    match config_map
         .data
         .and_then(|mut data| data.remove("DRUID_SQLALCHEMY"))
-        .context(MissingDruidConnStringSnafu)
+    {
+        Some(sqlalchemy_uri) => Ok(ImportPrerequisites::Available {
+            superset_db,
+            sqlalchemy_uri,
+        }),
+        None => Ok(ImportPrerequisites::Blocked {
+            reason: DruidConnectionPendingReason::DruidConnectionStringMissing,
+            message: format!(
+                "The Druid discovery ConfigMap {}/{} contains no DRUID_SQLALCHEMY",
+                druid.namespace, druid.name
+            ),
+        }),
+    }
 }
 
 /// Returns a yaml document read to be imported with "superset import-datasources"
 fn build_druid_db_yaml(druid_cluster_name: &str, sqlalchemy_str: &str) -> Result<String> {
-    Ok(format!(
-        "databases:\n- database_name: {}\n  sqlalchemy_uri: {}\n  tables: []\n",
-        druid_cluster_name, sqlalchemy_str
-    ))
+    serde_yaml::to_string(&json!({
+        "databases": [{
+            "database_name": druid_cluster_name,
+            "sqlalchemy_uri": sqlalchemy_str,
+            "tables": [],
+        }],
+    }))
+    .context(SerializeImportDocumentSnafu)
 }
 
-/// Builds the import job.  When run it will import the druid connection into the database.
-async fn build_import_job(
+/// Applies the ConfigMap with the `superset_config.py` and the document to import which are
+/// mounted into the import job
+async fn apply_import_config_map(
+    client: &Client,
     druid_connection: &DruidConnection,
     superset_db: &SupersetDB,
     sqlalchemy_str: &str,
+) -> Result<()> {
+    let config_map = build_import_config_map(druid_connection, superset_db, sqlalchemy_str)?;
+    client
+        .apply_patch(FIELD_MANAGER_SCOPE, &config_map, &config_map)
+        .await
+        .context(ApplyImportConfigMapSnafu)?;
+    Ok(())
+}
+
+/// Builds the ConfigMap with the `superset_config.py` and the document to import which are
+/// mounted into the import job
+pub fn build_import_config_map(
+    druid_connection: &DruidConnection,
+    superset_db: &SupersetDB,
+    sqlalchemy_str: &str,
+) -> Result<ConfigMap> {
+    let credentials = superset_db.credentials().context(InvalidCredentialsSnafu)?;
+    let config = superset_db
+        .config_snippets()
+        .and_then(|snippets| superset_job_config(&credentials, snippets))
+        .context(InvalidConfigSnippetsSnafu)?;
+    Ok(ConfigMap {
+        metadata: ObjectMetaBuilder::new()
+            .name(druid_connection.job_config_map_name())
+            .namespace_opt(druid_connection.namespace())
+            .ownerreference_from_resource(druid_connection, None, Some(true))
+            .context(ObjectMissingMetadataForOwnerRefSnafu)?
+            .build(),
+        data: Some(
+            [
+                (SUPERSET_CONFIG_FILENAME.to_string(), config),
+                (
+                    IMPORT_FILENAME.to_string(),
+                    build_druid_db_yaml(&druid_connection.spec.druid.name, sqlalchemy_str)?,
+                ),
+            ]
+            .into(),
+        ),
+        ..ConfigMap::default()
+    })
+}
+
+/// Builds the import job.  When run it will import the druid connection into the database.
+pub fn build_import_job(
+    druid_connection: &DruidConnection,
+    superset_db: &SupersetDB,
 ) -> Result<Job> {
-    let mut commands = vec![];
-
-    let config = "import os; SQLALCHEMY_DATABASE_URI = os.environ.get('DATABASE_URI')";
-    commands.push(format!("mkdir -p {PYTHONPATH}"));
-    commands.push(format!(
-        "echo \"{config}\" > {PYTHONPATH}/{SUPERSET_CONFIG_FILENAME}"
-    ));
-
-    let druid_info = build_druid_db_yaml(&druid_connection.spec.druid.name, sqlalchemy_str)?;
-    commands.push(format!("echo \"{}\" > /tmp/druids.yaml", druid_info));
-    commands.push(String::from(
-        "superset import_datasources -p /tmp/druids.yaml",
-    ));
-
-    let secret = &superset_db.spec.credentials_secret;
-
-    let container = ContainerBuilder::new("superset-import-druid-connection")
-        .image(format!(
-            "docker.stackable.tech/stackable/superset:{}-stackable1",
-            superset_db.spec.superset_version
-        ))
+    let credentials = superset_db.credentials().context(InvalidCredentialsSnafu)?;
+
+    let command = with_failure_summary(
+        "import_datasources",
+        &format!("superset import_datasources -p {PYTHONPATH}/{IMPORT_FILENAME}"),
+    );
+
+    let mut cb = ContainerBuilder::new("superset-import-druid-connection");
+    cb.image(superset_db.superset_image())
         .command(vec!["/bin/sh".to_string()])
-        .args(vec![String::from("-c"), commands.join("; ")])
-        .add_env_var_from_secret("DATABASE_URI", secret, "connections.sqlalchemyDatabaseUri")
-        .build();
+        .args(vec![String::from("-c"), command]);
+    add_superset_config_env(&mut cb, &credentials);
+    let mut container = cb.build();
+    // Failures which are not summarized by the script are explained by the end of the log
+    container.termination_message_policy = Some("FallbackToLogsOnError".to_string());
 
+    let mut pod_spec = PodSpec {
+        containers: vec![container],
+        restart_policy: Some("Never".to_string()),
+        ..Default::default()
+    };
+    add_job_config_volume(
+        &mut pod_spec,
+        "superset-import-druid-connection",
+        &druid_connection.job_config_map_name(),
+        &superset_db.spec.config_snippets,
+    );
+    add_metadata_database_tls(
+        &mut pod_spec,
+        "superset-import-druid-connection",
+        &credentials.metadata_database,
+    );
+    if let Some(python_packages) = &superset_db.spec.python_packages {
+        add_python_packages(
+            &mut pod_spec,
+            "superset-import-druid-connection",
+            python_packages,
+        );
+    }
+    add_image_pull_settings(&mut pod_spec, &superset_db.product_image());
     let pod = PodTemplateSpec {
         metadata: Some(
             ObjectMetaBuilder::new()
                 .name(druid_connection.job_name())
                 .build(),
         ),
-        spec: Some(PodSpec {
-            containers: vec![container],
-            restart_policy: Some("Never".to_string()),
-            ..Default::default()
-        }),
+        spec: Some(pod_spec),
     };
 
     let job = Job {
@@ -275,16 +517,50 @@ async fn build_import_job(
             .ownerreference_from_resource(druid_connection, None, Some(true))
             .context(ObjectMissingMetadataForOwnerRefSnafu)?
             .build(),
-        spec: Some(JobSpec {
-            template: pod,
-            ..Default::default()
-        }),
+        spec: Some(job_spec(pod, druid_connection.spec.job.as_ref())),
         status: None,
     };
 
     Ok(job)
 }
 
-pub fn error_policy(_error: &Error, _ctx: Context<Ctx>) -> Action {
+pub fn error_policy(error: &Error, ctx: Context<Ctx>) -> Action {
+    ctx.get_ref()
+        .metrics
+        .reconcile_failed(DRUID_CONNECTION_CONTROLLER, error.category());
     Action::requeue(Duration::from_secs(5))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(yaml: &str) -> serde_yaml::Value {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn druid_db_yaml_lists_the_druid_database() {
+        let yaml = build_druid_db_yaml("druid", "druid://druid-router:8888/druid/v2/sql").unwrap();
+        assert_eq!(
+            parse(&yaml),
+            parse(
+                "databases:\n\
+                 - database_name: druid\n  \
+                   sqlalchemy_uri: druid://druid-router:8888/druid/v2/sql\n  \
+                   tables: []\n"
+            )
+        );
+    }
+
+    #[test]
+    fn druid_db_yaml_keeps_ambiguous_values_strings() {
+        // Values which YAML would otherwise read as booleans, numbers or comments
+        for value in ["yes", "1.0", "druid://host:8888/#sql", "null"] {
+            let yaml = build_druid_db_yaml(value, value).unwrap();
+            let database = &parse(&yaml)["databases"][0];
+            assert_eq!(database["database_name"].as_str(), Some(value));
+            assert_eq!(database["sqlalchemy_uri"].as_str(), Some(value));
+        }
+    }
+}
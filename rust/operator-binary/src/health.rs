@@ -0,0 +1,201 @@
+//! Health of the controllers which is served on `/healthz` and `/readyz`
+//!
+//! The health is derived from what the controllers emit themselves: a controller is synced with
+//! its first reconciliation, or as soon as it is known that it has no objects to reconcile, and
+//! it makes progress as long as it reconciles its objects. All objects are reconciled again every
+//! [`RESYNC_INTERVAL`], so a controller with objects which has not reconciled for a while has
+//! stopped making progress. Watch errors are taken from the queue errors of the controllers.
+
+use futures::Stream;
+use stackable_operator::kube::{
+    api::{Api, ListParams},
+    runtime::{controller, reflector::Store},
+    Resource,
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// All objects of a controller are reconciled in this interval, see [`resync_trigger`]
+pub const RESYNC_INTERVAL: Duration = Duration::from_secs(300);
+/// The operator is restarted if a controller with objects has not reconciled within this period
+const PROGRESS_TIMEOUT: Duration = Duration::from_secs(900);
+/// The watches of a controller are considered recovered if no error occurred within this period
+const WATCH_RECOVERY_PERIOD: Duration = Duration::from_secs(60);
+/// The operator is restarted if the watches of a controller fail for longer than this period and
+/// no reconciliation happened within it
+const WATCH_FAILURE_TIMEOUT: Duration = Duration::from_secs(300);
+/// Delay before the check whether a controller has objects is repeated after an error
+const LIST_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+struct ControllerHealth {
+    running: bool,
+    synced: bool,
+    /// Number of objects in the reflector store of the controller
+    objects: Box<dyn Fn() -> usize + Send + Sync>,
+    /// Time of the last reconciliation, or of the start if there was none yet
+    last_reconcile: Instant,
+    /// Time of the first error since the watches last recovered
+    failing_since: Option<Instant>,
+    last_watch_error: Option<Instant>,
+}
+
+impl ControllerHealth {
+    fn failing(&self, now: Instant) -> bool {
+        self.last_watch_error
+            .map_or(false, |last| now - last < WATCH_RECOVERY_PERIOD)
+    }
+
+    fn ready(&self, now: Instant) -> Result<(), &'static str> {
+        if !self.running {
+            Err("stopped")
+        } else if self.failing(now) {
+            Err("watch failing")
+        } else if !self.synced {
+            Err("not synced")
+        } else {
+            Ok(())
+        }
+    }
+
+    fn live(&self, now: Instant) -> Result<(), &'static str> {
+        if !self.running {
+            Err("stopped")
+        } else if self.failing(now)
+            && self.failing_since.map_or(false, |since| {
+                now - since.max(self.last_reconcile) >= WATCH_FAILURE_TIMEOUT
+            })
+        {
+            Err("watch failing")
+        } else if now - self.last_reconcile >= PROGRESS_TIMEOUT && (self.objects)() > 0 {
+            Err("not reconciling")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Health {
+    controllers: Mutex<BTreeMap<&'static str, ControllerHealth>>,
+}
+
+impl Health {
+    /// Adds a controller whose objects are kept in the given reflector store
+    pub fn register<K>(&self, controller: &'static str, store: Store<K>)
+    where
+        K: Resource<DynamicType = ()> + Clone + Send + Sync + 'static,
+    {
+        self.controllers.lock().unwrap().insert(
+            controller,
+            ControllerHealth {
+                running: true,
+                synced: false,
+                objects: Box::new(move || store.state().len()),
+                last_reconcile: Instant::now(),
+                failing_since: None,
+                last_watch_error: None,
+            },
+        );
+    }
+
+    /// Marks the given controller as synced if there are no objects for it to reconcile,
+    /// otherwise it is synced with its first reconciliation
+    pub async fn sync_if_empty<K>(self: Arc<Self>, controller: &'static str, api: Api<K>)
+    where
+        K: Resource + Clone + serde::de::DeserializeOwned + Debug,
+    {
+        loop {
+            match api.list(&ListParams::default().limit(1)).await {
+                Ok(objects) => {
+                    if objects.items.is_empty() {
+                        self.update(controller, |health| health.synced = true);
+                    }
+                    return;
+                }
+                Err(error) => {
+                    tracing::warn!(%error, controller, "Failed to list the objects");
+                    tokio::time::sleep(LIST_RETRY_DELAY).await;
+                }
+            }
+        }
+    }
+
+    /// Records a result which the given controller emitted. Queue errors are failures of the
+    /// watches of the controller, any other result is the outcome of a reconciliation.
+    pub fn observe<ReconcilerErr, QueueErr>(
+        &self,
+        controller: &str,
+        result: &Result<impl Sized, controller::Error<ReconcilerErr, QueueErr>>,
+    ) where
+        ReconcilerErr: std::error::Error + 'static,
+        QueueErr: std::error::Error + 'static,
+    {
+        let now = Instant::now();
+        self.update(controller, |health| match result {
+            Err(controller::Error::QueueError(_)) => {
+                if !health.failing(now) {
+                    health.failing_since = Some(now);
+                }
+                health.last_watch_error = Some(now);
+            }
+            _ => {
+                health.synced = true;
+                health.last_reconcile = now;
+            }
+        });
+    }
+
+    /// Records that the stream of the given controller has ended
+    pub fn stopped(&self, controller: &str) {
+        self.update(controller, |health| health.running = false);
+    }
+
+    fn update(&self, controller: &str, f: impl FnOnce(&mut ControllerHealth)) {
+        if let Some(health) = self.controllers.lock().unwrap().get_mut(controller) {
+            f(health);
+        }
+    }
+
+    /// Returns whether all controllers are ready and a report per controller
+    pub fn readiness(&self) -> (bool, String) {
+        self.report(ControllerHealth::ready)
+    }
+
+    /// Returns whether all controllers are alive and a report per controller
+    pub fn liveness(&self) -> (bool, String) {
+        self.report(ControllerHealth::live)
+    }
+
+    fn report(
+        &self,
+        check: impl Fn(&ControllerHealth, Instant) -> Result<(), &'static str>,
+    ) -> (bool, String) {
+        let now = Instant::now();
+        let mut healthy = true;
+        let mut report = String::new();
+        for (controller, health) in self.controllers.lock().unwrap().iter() {
+            let state = match check(health, now) {
+                Ok(()) => "ok",
+                Err(reason) => {
+                    healthy = false;
+                    reason
+                }
+            };
+            report.push_str(&format!("{controller}: {state}\n"));
+        }
+        (healthy, report)
+    }
+}
+
+/// Emits every [`RESYNC_INTERVAL`], so that a controller which is built with
+/// `reconcile_all_on(resync_trigger())` reconciles all its objects periodically
+pub fn resync_trigger() -> impl Stream<Item = ()> + Send + Sync + 'static {
+    futures::stream::unfold((), |()| async {
+        tokio::time::sleep(RESYNC_INTERVAL).await;
+        Some(((), ()))
+    })
+}
@@ -0,0 +1,254 @@
+//! Lease-based leader election which allows running several replicas of the operator of which only
+//! one reconciles
+//!
+//! The leader stops reconciling when it cannot renew the Lease within the renew deadline. Because
+//! the renew deadline is shorter than the lease duration, the Lease cannot be taken over by another
+//! replica before the previous leader has stopped. Reconciliations which are interrupted at that
+//! point are repeated by the new leader, the Jobs and status transitions are applied idempotently.
+
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use snafu::{ensure, ResultExt, Snafu};
+use stackable_operator::{
+    k8s_openapi::{
+        api::coordination::v1::{Lease, LeaseSpec},
+        apimachinery::pkg::apis::meta::v1::MicroTime,
+        chrono::{self, Utc},
+    },
+    kube::{
+        api::{Api, PostParams},
+        core::ObjectMeta,
+    },
+};
+use std::time::{Duration, Instant};
+
+/// Interval in which a candidate tries to acquire the Lease and the leader renews it
+const RETRY_PERIOD: Duration = Duration::from_secs(2);
+
+#[derive(Snafu, Debug)]
+pub enum Error {
+    #[snafu(display("the renew deadline must be shorter than the lease duration"))]
+    InvalidRenewDeadline,
+    #[snafu(display("failed to get the Lease {name}"))]
+    GetLease {
+        source: stackable_operator::kube::Error,
+        name: String,
+    },
+    #[snafu(display("failed to create the Lease {name}"))]
+    CreateLease {
+        source: stackable_operator::kube::Error,
+        name: String,
+    },
+    #[snafu(display("failed to update the Lease {name}"))]
+    UpdateLease {
+        source: stackable_operator::kube::Error,
+        name: String,
+    },
+    #[snafu(display("the Lease {name} was taken over by another replica"))]
+    LeadershipLost { name: String },
+    #[snafu(display("the Lease {name} could not be renewed within the renew deadline"))]
+    RenewDeadlineExceeded { name: String },
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Returns the identity of this replica in the Lease
+///
+/// The pod name is taken from `HOSTNAME`. Without it the application name gets a random suffix, so
+/// that two replicas never share an identity and both consider themselves the leader.
+pub fn identity(app_name: &str) -> String {
+    std::env::var("HOSTNAME").unwrap_or_else(|_| {
+        let suffix: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(8)
+            .map(|c| char::from(c).to_ascii_lowercase())
+            .collect();
+        format!("{app_name}-{suffix}")
+    })
+}
+
+pub struct LeaderElector {
+    api: Api<Lease>,
+    name: String,
+    identity: String,
+    lease_duration: Duration,
+    renew_deadline: Duration,
+}
+
+impl LeaderElector {
+    pub fn new(
+        api: Api<Lease>,
+        name: String,
+        identity: String,
+        lease_duration: Duration,
+        renew_deadline: Duration,
+    ) -> Result<Self> {
+        ensure!(renew_deadline < lease_duration, InvalidRenewDeadlineSnafu);
+        Ok(Self {
+            api,
+            name,
+            identity,
+            lease_duration,
+            renew_deadline,
+        })
+    }
+
+    /// Waits until this replica holds the Lease
+    pub async fn acquire(&self) -> Result<()> {
+        tracing::info!(lease = %self.name, identity = %self.identity, "Waiting for the leadership");
+        loop {
+            match self.try_acquire_or_renew().await {
+                Ok(true) => {
+                    tracing::info!(lease = %self.name, "Acquired the leadership");
+                    return Ok(());
+                }
+                Ok(false) => (),
+                Err(error) => tracing::warn!(%error, "Failed to acquire the leadership"),
+            }
+            tokio::time::sleep(RETRY_PERIOD).await;
+        }
+    }
+
+    /// Renews the Lease and returns when the leadership is lost
+    ///
+    /// Each renewal is bounded by the rest of the renew deadline, so that a request which hangs
+    /// cannot keep this replica reconciling after the Lease has expired.
+    pub async fn hold(&self) -> Error {
+        let mut last_renewal = Instant::now();
+        loop {
+            tokio::time::sleep(RETRY_PERIOD).await;
+            let remaining = self.renew_deadline.saturating_sub(last_renewal.elapsed());
+            match tokio::time::timeout(remaining, self.try_acquire_or_renew()).await {
+                Ok(Ok(true)) => last_renewal = Instant::now(),
+                Ok(Ok(false)) => {
+                    return Error::LeadershipLost {
+                        name: self.name.clone(),
+                    }
+                }
+                Ok(Err(error)) => {
+                    tracing::warn!(%error, "Failed to renew the leadership");
+                    if last_renewal.elapsed() >= self.renew_deadline {
+                        return Error::RenewDeadlineExceeded {
+                            name: self.name.clone(),
+                        };
+                    }
+                }
+                Err(_) => {
+                    return Error::RenewDeadlineExceeded {
+                        name: self.name.clone(),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Gives up the Lease so that another replica can take over without waiting for it to expire
+    pub async fn release(&self) -> Result<()> {
+        let mut lease = self
+            .api
+            .get(&self.name)
+            .await
+            .context(GetLeaseSnafu { name: &self.name })?;
+        let spec = lease.spec.get_or_insert_with(LeaseSpec::default);
+        if spec.holder_identity.as_ref() == Some(&self.identity) {
+            spec.holder_identity = None;
+            self.api
+                .replace(&self.name, &PostParams::default(), &lease)
+                .await
+                .context(UpdateLeaseSnafu { name: &self.name })?;
+            tracing::info!(lease = %self.name, "Released the leadership");
+        }
+        Ok(())
+    }
+
+    /// Takes the Lease if it is free or expired and renews it if it is held by this replica,
+    /// returns whether this replica holds the Lease afterwards
+    async fn try_acquire_or_renew(&self) -> Result<bool> {
+        let now = Utc::now();
+        let lease = match self.api.get(&self.name).await {
+            Ok(lease) => lease,
+            Err(stackable_operator::kube::Error::Api(response)) if response.code == 404 => {
+                let lease = Lease {
+                    metadata: ObjectMeta {
+                        name: Some(self.name.clone()),
+                        ..ObjectMeta::default()
+                    },
+                    spec: Some(self.lease_spec(now, now, 0)),
+                };
+                return match self.api.create(&PostParams::default(), &lease).await {
+                    Ok(_) => Ok(true),
+                    Err(stackable_operator::kube::Error::Api(response)) if response.code == 409 => {
+                        Ok(false)
+                    }
+                    Err(source) => Err(Error::CreateLease {
+                        source,
+                        name: self.name.clone(),
+                    }),
+                };
+            }
+            Err(source) => {
+                return Err(Error::GetLease {
+                    source,
+                    name: self.name.clone(),
+                })
+            }
+        };
+
+        let spec = lease.spec.clone().unwrap_or_default();
+        let held_by_self = spec.holder_identity.as_ref() == Some(&self.identity);
+        if !held_by_self && spec.holder_identity.is_some() {
+            let expiry = spec.renew_time.as_ref().map(|MicroTime(renew_time)| {
+                *renew_time
+                    + chrono::Duration::seconds(
+                        spec.lease_duration_seconds.unwrap_or_default().into(),
+                    )
+            });
+            if expiry.map_or(false, |expiry| expiry > now) {
+                return Ok(false);
+            }
+        }
+
+        let (acquire_time, transitions) = if held_by_self {
+            (
+                spec.acquire_time.map_or(now, |MicroTime(time)| time),
+                spec.lease_transitions.unwrap_or_default(),
+            )
+        } else {
+            (now, spec.lease_transitions.unwrap_or_default() + 1)
+        };
+        let updated = Lease {
+            spec: Some(self.lease_spec(acquire_time, now, transitions)),
+            ..lease
+        };
+        // The resource version of the fetched Lease makes the update fail if another replica has
+        // changed it in the meantime
+        match self
+            .api
+            .replace(&self.name, &PostParams::default(), &updated)
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(stackable_operator::kube::Error::Api(response)) if response.code == 409 => {
+                Ok(false)
+            }
+            Err(source) => Err(Error::UpdateLease {
+                source,
+                name: self.name.clone(),
+            }),
+        }
+    }
+
+    fn lease_spec(
+        &self,
+        acquire_time: chrono::DateTime<Utc>,
+        renew_time: chrono::DateTime<Utc>,
+        transitions: i32,
+    ) -> LeaseSpec {
+        LeaseSpec {
+            holder_identity: Some(self.identity.clone()),
+            lease_duration_seconds: Some(self.lease_duration.as_secs() as i32),
+            acquire_time: Some(MicroTime(acquire_time)),
+            renew_time: Some(MicroTime(renew_time)),
+            lease_transitions: Some(transitions),
+        }
+    }
+}
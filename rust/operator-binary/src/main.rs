@@ -1,27 +1,50 @@
 mod druid_connection_controller;
+mod health;
+mod leader_election;
+mod metrics;
+mod render;
+mod server;
 mod superset_controller;
+mod superset_db_backup_controller;
 mod superset_db_controller;
+mod superset_db_restore_controller;
 mod util;
 
+use crate::{
+    health::{resync_trigger, Health},
+    leader_election::LeaderElector,
+    metrics::{
+        Metrics, DRUID_CONNECTION_CONTROLLER, SUPERSET_CONTROLLER, SUPERSET_DB_BACKUP_CONTROLLER,
+        SUPERSET_DB_CONTROLLER, SUPERSET_DB_RESTORE_CONTROLLER,
+    },
+};
+
 use clap::Parser;
 use futures::StreamExt;
 use stackable_operator::{
-    cli::{Command, ProductOperatorRun},
+    cli::{Command, ProductConfigPath, ProductOperatorRun},
     k8s_openapi::api::{
         apps::v1::StatefulSet,
         batch::v1::Job,
-        core::v1::{Secret, Service},
+        core::v1::{ConfigMap, Pod, Secret, Service},
     },
     kube::{
-        api::ListParams,
+        api::{Api, ListParams},
         runtime::{controller::Context, reflector::ObjectRef, Controller},
-        CustomResourceExt,
+        CustomResourceExt, Resource, ResourceExt,
     },
+    labels::{APP_COMPONENT_LABEL, APP_INSTANCE_LABEL, APP_NAME_LABEL},
     logging::controller::report_controller_reconciled,
 };
 use stackable_superset_crd::{
-    druidconnection::DruidConnection, supersetdb::SupersetDB, SupersetCluster,
+    druidconnection::DruidConnection,
+    supersetdb::{InitPhase, SupersetDB},
+    supersetdbbackup::SupersetDBBackup,
+    supersetdbrestore::SupersetDBRestore,
+    SupersetCluster, SupersetRole,
 };
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+use strum::IntoEnumIterator;
 
 mod built_info {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
@@ -30,28 +53,97 @@ mod built_info {
 pub const APP_NAME: &str = "superset";
 pub const APP_PORT: u16 = 8088;
 
+/// The Lease which is held by the elected leader of the operator replicas
+const LEASE_NAME: &str = "superset-operator-leader";
+
+const PRODUCT_CONFIG_SEARCH_PATHS: [&str; 2] = [
+    "deploy/config-spec/properties.yaml",
+    "/etc/stackable/superset-operator/config-spec/properties.yaml",
+];
+
 #[derive(Parser)]
 #[clap(about = built_info::PKG_DESCRIPTION, author = stackable_operator::cli::AUTHOR)]
 struct Opts {
     #[clap(subcommand)]
-    cmd: Command,
+    cmd: SupersetCommand,
+}
+
+#[derive(clap::Subcommand)]
+enum SupersetCommand {
+    #[clap(flatten)]
+    Operator(Command<SupersetOperatorRun>),
+    /// Print the objects which the operator creates for the custom resources in a file, without
+    /// contacting a cluster
+    Render(SupersetOperatorRender),
+}
+
+#[derive(Parser)]
+struct SupersetOperatorRun {
+    #[clap(flatten)]
+    common: ProductOperatorRun,
+    /// Address on which the metrics and the health endpoints are served
+    #[clap(long, default_value = "0.0.0.0:8080")]
+    http_address: SocketAddr,
+    /// Elect a leader among several replicas of the operator, only the leader reconciles
+    #[clap(long)]
+    leader_election: bool,
+    /// Namespace of the Lease which is used for the leader election
+    #[clap(long, env = "POD_NAMESPACE", default_value = "default")]
+    leader_election_namespace: String,
+    /// Seconds after which the Lease of a leader which stopped renewing it can be taken over
+    #[clap(long, default_value = "15")]
+    lease_duration: u64,
+    /// Seconds for which the leader retries renewing the Lease before it stops reconciling, must
+    /// be shorter than the lease duration
+    #[clap(long, default_value = "10")]
+    renew_deadline: u64,
+}
+
+#[derive(Parser)]
+struct SupersetOperatorRender {
+    /// YAML file with a SupersetCluster and optionally SupersetDBs and DruidConnections
+    #[clap(parse(from_os_str))]
+    file: PathBuf,
+    /// Provides the path to a product-config file
+    #[clap(
+        long,
+        short = 'p',
+        value_name = "FILE",
+        default_value = "",
+        parse(from_os_str)
+    )]
+    product_config: ProductConfigPath,
+    /// Connection string of Druid which the operator reads from the discovery ConfigMap, a
+    /// placeholder is rendered if it is not given
+    #[clap(long)]
+    druid_sqlalchemy_uri: Option<String>,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let opts = Opts::parse();
     match opts.cmd {
-        Command::Crd => println!(
-            "{}{}{}",
+        SupersetCommand::Operator(Command::Crd) => println!(
+            "{}{}{}{}{}",
             serde_yaml::to_string(&SupersetCluster::crd())?,
             serde_yaml::to_string(&SupersetDB::crd())?,
-            serde_yaml::to_string(&DruidConnection::crd())?
+            serde_yaml::to_string(&DruidConnection::crd())?,
+            serde_yaml::to_string(&SupersetDBBackup::crd())?,
+            serde_yaml::to_string(&SupersetDBRestore::crd())?
         ),
-        Command::Run(ProductOperatorRun {
-            product_config,
-            watch_namespace,
-            tracing_target,
-        }) => {
+        SupersetCommand::Operator(Command::Run(SupersetOperatorRun {
+            common:
+                ProductOperatorRun {
+                    product_config,
+                    watch_namespace,
+                    tracing_target,
+                },
+            http_address,
+            leader_election,
+            leader_election_namespace,
+            lease_duration,
+            renew_deadline,
+        })) => {
             stackable_operator::utils::print_startup_string(
                 built_info::PKG_DESCRIPTION,
                 built_info::PKG_VERSION,
@@ -66,44 +158,133 @@ async fn main() -> anyhow::Result<()> {
                 tracing_target,
             );
 
-            let product_config = product_config.load(&[
-                "deploy/config-spec/properties.yaml",
-                "/etc/stackable/superset-operator/config-spec/properties.yaml",
-            ])?;
+            let product_config = product_config.load(&PRODUCT_CONFIG_SEARCH_PATHS)?;
 
             let client = stackable_operator::client::create_client(Some(
                 "superset.stackable.tech".to_string(),
             ))
             .await?;
 
-            let superset_controller = Controller::new(
+            let leader_elector = if leader_election {
+                Some(LeaderElector::new(
+                    Api::namespaced(client.as_kube_client(), &leader_election_namespace),
+                    LEASE_NAME.to_string(),
+                    leader_election::identity(APP_NAME),
+                    Duration::from_secs(lease_duration),
+                    Duration::from_secs(renew_deadline),
+                )?)
+            } else {
+                None
+            };
+
+            let metrics = Arc::new(Metrics::new()?);
+            let health = Arc::new(Health::default());
+
+            let superset_controller_builder = Controller::new(
                 watch_namespace.get_api::<SupersetCluster>(&client),
                 ListParams::default(),
-            )
-            .owns(
-                watch_namespace.get_api::<Service>(&client),
-                ListParams::default(),
-            )
-            .owns(
-                watch_namespace.get_api::<StatefulSet>(&client),
-                ListParams::default(),
-            )
-            .shutdown_on_signal()
-            .run(
-                superset_controller::reconcile_superset,
-                superset_controller::error_policy,
-                Context::new(superset_controller::Ctx {
-                    client: client.clone(),
-                    product_config,
-                }),
-            )
-            .map(|res| {
-                report_controller_reconciled(
-                    &client,
-                    "supersetclusters.superset.stackable.tech",
-                    &res,
+            );
+            let superset_store1 = superset_controller_builder.store();
+            let superset_store2 = superset_controller_builder.store();
+            health.register(SUPERSET_CONTROLLER, superset_controller_builder.store());
+            let superset_controller = superset_controller_builder
+                .reconcile_all_on(resync_trigger())
+                .watches(
+                    watch_namespace.get_api::<Service>(&client),
+                    ListParams::default(),
+                    metrics.count_triggers(SUPERSET_CONTROLLER, owners::<SupersetCluster, Service>),
+                )
+                .watches(
+                    watch_namespace.get_api::<StatefulSet>(&client),
+                    ListParams::default(),
+                    metrics.count_triggers(
+                        SUPERSET_CONTROLLER,
+                        owners::<SupersetCluster, StatefulSet>,
+                    ),
+                )
+                // The pods are rolled when the secret key re-encryption Job has finished
+                .watches(
+                    watch_namespace.get_api::<Job>(&client),
+                    ListParams::default(),
+                    metrics.count_triggers(SUPERSET_CONTROLLER, owners::<SupersetCluster, Job>),
+                )
+                // Rotated secret keys and certificates are detected, and a deleted generated
+                // credentials Secret is created again
+                .watches(
+                    watch_namespace.get_api::<Secret>(&client),
+                    ListParams::default(),
+                    metrics.count_triggers(SUPERSET_CONTROLLER, move |secret: Secret| {
+                        superset_store1
+                            .state()
+                            .into_iter()
+                            .filter(move |superset| {
+                                if let (Some(n), Ok(credentials)) =
+                                    (&secret.metadata.name, superset.credentials())
+                                {
+                                    superset.metadata.namespace == secret.metadata.namespace
+                                        && (credentials.references_secret(n)
+                                            || superset.tls_secret_name() == Some(n.as_str()))
+                                } else {
+                                    false
+                                }
+                            })
+                            .map(|superset| ObjectRef::from_obj(&*superset))
+                    }),
+                )
+                // Changed config snippets roll the pods
+                .watches(
+                    watch_namespace.get_api::<ConfigMap>(&client),
+                    ListParams::default(),
+                    metrics.count_triggers(SUPERSET_CONTROLLER, move |config_map: ConfigMap| {
+                        superset_store2
+                            .state()
+                            .into_iter()
+                            .filter(move |superset| {
+                                superset.metadata.namespace == config_map.metadata.namespace
+                                    && superset.spec.config_snippets.iter().any(|snippet| {
+                                        config_map.metadata.name.as_ref()
+                                            == Some(&snippet.config_map)
+                                    })
+                            })
+                            .map(|superset| ObjectRef::from_obj(&*superset))
+                    }),
+                )
+                // Failed installations of the Python packages are reported in the status
+                .watches(
+                    watch_namespace.get_api::<Pod>(&client),
+                    ListParams::default().labels(&format!(
+                        "{APP_NAME_LABEL}={APP_NAME},{APP_COMPONENT_LABEL}={}",
+                        SupersetRole::Node
+                    )),
+                    metrics.count_triggers(SUPERSET_CONTROLLER, |pod: Pod| {
+                        pod.labels().get(APP_INSTANCE_LABEL).map(|instance| {
+                            ObjectRef::<SupersetCluster>::new(instance)
+                                .within(&pod.namespace().unwrap_or_default())
+                        })
+                    }),
                 )
-            });
+                .shutdown_on_signal()
+                .run(
+                    superset_controller::reconcile_superset,
+                    superset_controller::error_policy,
+                    Context::new(superset_controller::Ctx {
+                        client: client.clone(),
+                        product_config,
+                        metrics: metrics.clone(),
+                    }),
+                )
+                .map(|res| {
+                    health.observe(SUPERSET_CONTROLLER, &res);
+                    metrics.observe(SUPERSET_CONTROLLER, &res);
+                    report_controller_reconciled(
+                        &client,
+                        "supersetclusters.superset.stackable.tech",
+                        &res,
+                    )
+                })
+                .chain(futures::stream::once(async {
+                    health.stopped(SUPERSET_CONTROLLER)
+                }));
 
             let superset_db_controller_builder = Controller::new(
                 watch_namespace.get_api::<SupersetDB>(&client),
@@ -111,57 +292,95 @@ async fn main() -> anyhow::Result<()> {
             );
             let superset_db_store1 = superset_db_controller_builder.store();
             let superset_db_store2 = superset_db_controller_builder.store();
+            let superset_db_store3 = superset_db_controller_builder.store();
+            let superset_db_store4 = superset_db_controller_builder.store();
+            health.register(
+                SUPERSET_DB_CONTROLLER,
+                superset_db_controller_builder.store(),
+            );
             let superset_db_controller = superset_db_controller_builder
+                .reconcile_all_on(resync_trigger())
                 .shutdown_on_signal()
                 .watches(
                     watch_namespace.get_api::<Secret>(&client),
                     ListParams::default(),
-                    move |secret| {
+                    metrics.count_triggers(SUPERSET_DB_CONTROLLER, move |secret: Secret| {
                         superset_db_store1
                             .state()
                             .into_iter()
                             .filter(move |superset_db| {
-                                if let Some(n) = &secret.metadata.name {
-                                    &superset_db.spec.credentials_secret == n
+                                if let (Some(n), Ok(credentials)) =
+                                    (&secret.metadata.name, superset_db.credentials())
+                                {
+                                    credentials.references_secret(n)
                                 } else {
                                     false
                                 }
                             })
                             .map(|superset_db| ObjectRef::from_obj(&*superset_db))
-                    },
+                    }),
                 )
                 // We have to watch jobs so we can react to finished init jobs
                 // and update our status accordingly
                 .watches(
                     watch_namespace.get_api::<Job>(&client),
                     ListParams::default(),
-                    move |job| {
+                    metrics.count_triggers(SUPERSET_DB_CONTROLLER, move |job: Job| {
                         superset_db_store2
                             .state()
                             .into_iter()
                             .filter(move |superset_db| {
+                                let job_name = job.metadata.name.as_ref().unwrap();
                                 superset_db.metadata.namespace.as_ref().unwrap()
                                     == job.metadata.namespace.as_ref().unwrap()
-                                    && &superset_db.job_name()
-                                        == job.metadata.name.as_ref().unwrap()
+                                    && (InitPhase::iter().any(|phase| {
+                                        &superset_db.phase_job_name(phase) == job_name
+                                    }) || &superset_db.drop_job_name() == job_name
+                                        // The Job of an initialization without phases
+                                        || &superset_db.name() == job_name)
                             })
                             .map(|superset_db| ObjectRef::from_obj(&*superset_db))
-                    },
+                    }),
+                )
+                // Migrations wait for their pre-migration backup to finish
+                .watches(
+                    watch_namespace.get_api::<SupersetDBBackup>(&client),
+                    ListParams::default(),
+                    metrics.count_triggers(
+                        SUPERSET_DB_CONTROLLER,
+                        move |backup: SupersetDBBackup| {
+                            superset_db_store3
+                                .state()
+                                .into_iter()
+                                .filter(move |superset_db| {
+                                    superset_db.metadata.namespace == backup.metadata.namespace
+                                        && superset_db.metadata.name.as_ref()
+                                            == Some(&backup.spec.superset_db)
+                                })
+                                .map(|superset_db| ObjectRef::from_obj(&*superset_db))
+                        },
+                    ),
                 )
                 .run(
                     superset_db_controller::reconcile_superset_db,
                     superset_db_controller::error_policy,
                     Context::new(superset_db_controller::Ctx {
                         client: client.clone(),
+                        metrics: metrics.clone(),
                     }),
                 )
                 .map(|res| {
+                    health.observe(SUPERSET_DB_CONTROLLER, &res);
+                    metrics.observe(SUPERSET_DB_CONTROLLER, &res);
                     report_controller_reconciled(
                         &client,
                         "supersetdbclusters.superset.stackable.tech",
                         &res,
                     )
-                });
+                })
+                .chain(futures::stream::once(async {
+                    health.stopped(SUPERSET_DB_CONTROLLER)
+                }));
 
             let druid_connection_controller_builder = Controller::new(
                 watch_namespace.get_api::<DruidConnection>(&client),
@@ -169,12 +388,18 @@ async fn main() -> anyhow::Result<()> {
             );
             let druid_connection_store1 = druid_connection_controller_builder.store();
             let druid_connection_store2 = druid_connection_controller_builder.store();
+            let druid_connection_store3 = druid_connection_controller_builder.store();
+            health.register(
+                DRUID_CONNECTION_CONTROLLER,
+                druid_connection_controller_builder.store(),
+            );
             let druid_connection_controller = druid_connection_controller_builder
+                .reconcile_all_on(resync_trigger())
                 .shutdown_on_signal()
                 .watches(
                     watch_namespace.get_api::<SupersetDB>(&client),
                     ListParams::default(),
-                    move |sdb| {
+                    metrics.count_triggers(DRUID_CONNECTION_CONTROLLER, move |sdb: SupersetDB| {
                         druid_connection_store1
                             .state()
                             .into_iter()
@@ -185,12 +410,12 @@ async fn main() -> anyhow::Result<()> {
                                         == sdb.metadata.name.as_ref().unwrap()
                             })
                             .map(|druid_connection| ObjectRef::from_obj(&*druid_connection))
-                    },
+                    }),
                 )
                 .watches(
                     watch_namespace.get_api::<Job>(&client),
                     ListParams::default(),
-                    move |job| {
+                    metrics.count_triggers(DRUID_CONNECTION_CONTROLLER, move |job: Job| {
                         druid_connection_store2
                             .state()
                             .into_iter()
@@ -201,31 +426,209 @@ async fn main() -> anyhow::Result<()> {
                                         == job.metadata.name.as_ref().unwrap()
                             })
                             .map(|druid_connection| ObjectRef::from_obj(&*druid_connection))
-                    },
+                    }),
                 )
                 .run(
                     druid_connection_controller::reconcile_druid_connection,
                     druid_connection_controller::error_policy,
                     Context::new(druid_connection_controller::Ctx {
                         client: client.clone(),
+                        metrics: metrics.clone(),
                     }),
                 )
                 .map(|res| {
+                    health.observe(DRUID_CONNECTION_CONTROLLER, &res);
+                    metrics.observe(DRUID_CONNECTION_CONTROLLER, &res);
                     report_controller_reconciled(
                         &client,
                         "druidconnection.superset.stackable.tech",
                         &res,
                     )
-                });
+                })
+                .chain(futures::stream::once(async {
+                    health.stopped(DRUID_CONNECTION_CONTROLLER)
+                }));
+
+            let superset_db_backup_controller_builder = Controller::new(
+                watch_namespace.get_api::<SupersetDBBackup>(&client),
+                ListParams::default(),
+            );
+            health.register(
+                SUPERSET_DB_BACKUP_CONTROLLER,
+                superset_db_backup_controller_builder.store(),
+            );
+            let superset_db_backup_controller = superset_db_backup_controller_builder
+                .reconcile_all_on(resync_trigger())
+                .watches(
+                    watch_namespace.get_api::<Job>(&client),
+                    ListParams::default(),
+                    metrics.count_triggers(
+                        SUPERSET_DB_BACKUP_CONTROLLER,
+                        owners::<SupersetDBBackup, Job>,
+                    ),
+                )
+                .shutdown_on_signal()
+                .run(
+                    superset_db_backup_controller::reconcile_superset_db_backup,
+                    superset_db_backup_controller::error_policy,
+                    Context::new(superset_db_backup_controller::Ctx {
+                        client: client.clone(),
+                        metrics: metrics.clone(),
+                    }),
+                )
+                .map(|res| {
+                    health.observe(SUPERSET_DB_BACKUP_CONTROLLER, &res);
+                    metrics.observe(SUPERSET_DB_BACKUP_CONTROLLER, &res);
+                    report_controller_reconciled(
+                        &client,
+                        "supersetdbbackups.superset.stackable.tech",
+                        &res,
+                    )
+                })
+                .chain(futures::stream::once(async {
+                    health.stopped(SUPERSET_DB_BACKUP_CONTROLLER)
+                }));
+
+            let superset_db_restore_controller_builder = Controller::new(
+                watch_namespace.get_api::<SupersetDBRestore>(&client),
+                ListParams::default(),
+            );
+            health.register(
+                SUPERSET_DB_RESTORE_CONTROLLER,
+                superset_db_restore_controller_builder.store(),
+            );
+            let superset_db_restore_controller = superset_db_restore_controller_builder
+                .reconcile_all_on(resync_trigger())
+                .watches(
+                    watch_namespace.get_api::<Job>(&client),
+                    ListParams::default(),
+                    metrics.count_triggers(
+                        SUPERSET_DB_RESTORE_CONTROLLER,
+                        owners::<SupersetDBRestore, Job>,
+                    ),
+                )
+                .shutdown_on_signal()
+                .run(
+                    superset_db_restore_controller::reconcile_superset_db_restore,
+                    superset_db_restore_controller::error_policy,
+                    Context::new(superset_db_restore_controller::Ctx {
+                        client: client.clone(),
+                        metrics: metrics.clone(),
+                    }),
+                )
+                .map(|res| {
+                    health.observe(SUPERSET_DB_RESTORE_CONTROLLER, &res);
+                    metrics.observe(SUPERSET_DB_RESTORE_CONTROLLER, &res);
+                    report_controller_reconciled(
+                        &client,
+                        "supersetdbrestores.superset.stackable.tech",
+                        &res,
+                    )
+                })
+                .chain(futures::stream::once(async {
+                    health.stopped(SUPERSET_DB_RESTORE_CONTROLLER)
+                }));
 
-            futures::stream::select(
-                futures::stream::select(superset_controller, superset_db_controller),
-                druid_connection_controller,
+            // A controller without objects does not reconcile, so it would never become synced
+            tokio::spawn(health.clone().sync_if_empty(
+                SUPERSET_CONTROLLER,
+                watch_namespace.get_api::<SupersetCluster>(&client),
+            ));
+            tokio::spawn(health.clone().sync_if_empty(
+                SUPERSET_DB_CONTROLLER,
+                watch_namespace.get_api::<SupersetDB>(&client),
+            ));
+            tokio::spawn(health.clone().sync_if_empty(
+                DRUID_CONNECTION_CONTROLLER,
+                watch_namespace.get_api::<DruidConnection>(&client),
+            ));
+            tokio::spawn(health.clone().sync_if_empty(
+                SUPERSET_DB_BACKUP_CONTROLLER,
+                watch_namespace.get_api::<SupersetDBBackup>(&client),
+            ));
+            tokio::spawn(health.clone().sync_if_empty(
+                SUPERSET_DB_RESTORE_CONTROLLER,
+                watch_namespace.get_api::<SupersetDBRestore>(&client),
+            ));
+
+            let controllers = futures::stream::select(
+                futures::stream::select(
+                    futures::stream::select(superset_controller, superset_db_controller),
+                    druid_connection_controller,
+                ),
+                futures::stream::select(
+                    superset_db_backup_controller,
+                    superset_db_restore_controller,
+                ),
             )
-            .collect::<()>()
-            .await;
+            .collect::<()>();
+
+            let server = server::serve(
+                http_address,
+                server::State {
+                    health: health.clone(),
+                    metrics: metrics.clone(),
+                    superset_dbs: superset_db_store4,
+                    druid_connections: druid_connection_store3,
+                },
+            );
+
+            tokio::pin!(server);
+
+            // Standby replicas serve their health while they wait for the leadership
+            if let Some(leader_elector) = &leader_elector {
+                tokio::select! {
+                    result = &mut server => return Ok(result?),
+                    result = leader_elector.acquire() => result?,
+                }
+            }
+            let leadership = async {
+                match &leader_elector {
+                    Some(leader_elector) => leader_elector.hold().await,
+                    None => futures::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                () = controllers => (),
+                result = server => result?,
+                // The operator exits so that it is restarted as a candidate
+                error = leadership => return Err(error.into()),
+            }
+
+            if let Some(leader_elector) = &leader_elector {
+                leader_elector.release().await?;
+            }
+        }
+        SupersetCommand::Render(SupersetOperatorRender {
+            file,
+            product_config,
+            druid_sqlalchemy_uri,
+        }) => {
+            let product_config = product_config.load(&PRODUCT_CONFIG_SEARCH_PATHS)?;
+            print!(
+                "{}",
+                render::render(&file, &product_config, druid_sqlalchemy_uri.as_deref())?
+            );
         }
     }
 
     Ok(())
 }
+
+/// Maps an object to its owners of the kind `K`, like the `owns` relation of a controller
+fn owners<K, Child>(child: Child) -> Vec<ObjectRef<K>>
+where
+    K: Resource<DynamicType = ()>,
+    Child: Resource,
+{
+    let namespace = child.namespace().unwrap_or_default();
+    child
+        .meta()
+        .owner_references
+        .iter()
+        .flatten()
+        .filter(|owner| owner.kind == K::kind(&()) && owner.api_version == K::api_version(&()))
+        .map(|owner| ObjectRef::new(&owner.name).within(&namespace))
+        .collect()
+}
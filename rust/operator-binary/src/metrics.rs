@@ -0,0 +1,267 @@
+//! Prometheus metrics of the operator itself which are served on `/metrics`
+
+use crate::util::JobState;
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramTimer, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec,
+    Opts, Registry, TextEncoder,
+};
+use stackable_operator::kube::{
+    runtime::{
+        controller,
+        reflector::{ObjectRef, Store},
+    },
+    Resource, ResourceExt,
+};
+use stackable_superset_crd::{druidconnection::DruidConnection, supersetdb::SupersetDB};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+const NAMESPACE: &str = "superset_operator";
+
+pub const SUPERSET_CONTROLLER: &str = "supersetcluster";
+pub const SUPERSET_DB_CONTROLLER: &str = "supersetdb";
+pub const DRUID_CONNECTION_CONTROLLER: &str = "druidconnection";
+pub const SUPERSET_DB_BACKUP_CONTROLLER: &str = "supersetdbbackup";
+pub const SUPERSET_DB_RESTORE_CONTROLLER: &str = "supersetdbrestore";
+
+pub struct Metrics {
+    registry: Registry,
+    reconciliations: IntCounterVec,
+    reconcile_errors: IntCounterVec,
+    reconcile_duration: HistogramVec,
+    reconciliations_in_progress: IntGaugeVec,
+    reconcile_queue_depth: IntGaugeVec,
+    /// Namespace and name of the objects per controller whose reconciliation was triggered but
+    /// has not started yet
+    queued: Mutex<HashMap<&'static str, HashSet<(Option<String>, String)>>>,
+    superset_dbs: IntGaugeVec,
+    druid_connections: IntGaugeVec,
+    jobs: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new();
+        let reconciliations = IntCounterVec::new(
+            Opts::new("reconciliations_total", "Number of started reconciliations")
+                .namespace(NAMESPACE),
+            &["controller"],
+        )?;
+        let reconcile_errors = IntCounterVec::new(
+            Opts::new("reconcile_errors_total", "Number of failed reconciliations")
+                .namespace(NAMESPACE),
+            &["controller", "category"],
+        )?;
+        let reconcile_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "reconcile_duration_seconds",
+                "Duration of the reconciliations",
+            )
+            .namespace(NAMESPACE)
+            .buckets(vec![0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0]),
+            &["controller"],
+        )?;
+        let reconciliations_in_progress = IntGaugeVec::new(
+            Opts::new(
+                "reconciliations_in_progress",
+                "Number of reconciliations which are currently running",
+            )
+            .namespace(NAMESPACE),
+            &["controller"],
+        )?;
+        let reconcile_queue_depth = IntGaugeVec::new(
+            Opts::new(
+                "reconcile_queue_depth",
+                "Number of objects whose reconciliation was triggered by a watch event but has not started yet",
+            )
+            .namespace(NAMESPACE),
+            &["controller"],
+        )?;
+        let superset_dbs = IntGaugeVec::new(
+            Opts::new(
+                "superset_dbs",
+                "Number of SupersetDB objects per status condition",
+            )
+            .namespace(NAMESPACE),
+            &["condition"],
+        )?;
+        let druid_connections = IntGaugeVec::new(
+            Opts::new(
+                "druid_connections",
+                "Number of DruidConnection objects per status condition",
+            )
+            .namespace(NAMESPACE),
+            &["condition"],
+        )?;
+        let jobs = IntCounterVec::new(
+            Opts::new("jobs_total", "Number of finished Jobs per outcome").namespace(NAMESPACE),
+            &["controller", "job", "outcome"],
+        )?;
+
+        registry.register(Box::new(reconciliations.clone()))?;
+        registry.register(Box::new(reconcile_errors.clone()))?;
+        registry.register(Box::new(reconcile_duration.clone()))?;
+        registry.register(Box::new(reconciliations_in_progress.clone()))?;
+        registry.register(Box::new(reconcile_queue_depth.clone()))?;
+        registry.register(Box::new(superset_dbs.clone()))?;
+        registry.register(Box::new(druid_connections.clone()))?;
+        registry.register(Box::new(jobs.clone()))?;
+
+        Ok(Self {
+            registry,
+            reconciliations,
+            reconcile_errors,
+            reconcile_duration,
+            reconciliations_in_progress,
+            reconcile_queue_depth,
+            queued: Mutex::default(),
+            superset_dbs,
+            druid_connections,
+            jobs,
+        })
+    }
+
+    /// Wraps the mapper of a watch so that the reconciliations which it triggers are counted in the
+    /// queue of the given controller
+    pub fn count_triggers<T, K, I>(
+        self: &Arc<Self>,
+        controller: &'static str,
+        mapper: impl Fn(T) -> I + Send + Sync + 'static,
+    ) -> impl Fn(T) -> Vec<ObjectRef<K>> + Send + Sync + 'static
+    where
+        K: Resource,
+        I: IntoIterator<Item = ObjectRef<K>>,
+    {
+        let metrics = self.clone();
+        move |object| metrics.triggered(controller, mapper(object))
+    }
+
+    /// Adds the given objects to the queue of the controller, an object which is already queued
+    /// is reconciled only once
+    fn triggered<K: Resource>(
+        &self,
+        controller: &'static str,
+        objects: impl IntoIterator<Item = ObjectRef<K>>,
+    ) -> Vec<ObjectRef<K>> {
+        let objects = objects.into_iter().collect::<Vec<_>>();
+        let mut queued = self.queued.lock().unwrap();
+        let queue = queued.entry(controller).or_default();
+        queue.extend(
+            objects
+                .iter()
+                .map(|object| (object.namespace.clone(), object.name.clone())),
+        );
+        self.reconcile_queue_depth
+            .with_label_values(&[controller])
+            .set(queue.len() as i64);
+        objects
+    }
+
+    fn dequeue(&self, controller: &str, namespace: Option<String>, name: String) {
+        if let Some(queue) = self.queued.lock().unwrap().get_mut(controller) {
+            queue.remove(&(namespace, name));
+            self.reconcile_queue_depth
+                .with_label_values(&[controller])
+                .set(queue.len() as i64);
+        }
+    }
+
+    /// Removes an object whose reconciliation was dropped from the queue, e.g. because the object
+    /// was deleted before it was reconciled
+    pub fn observe<ReconcilerErr, QueueErr>(
+        &self,
+        controller: &str,
+        result: &Result<impl Sized, controller::Error<ReconcilerErr, QueueErr>>,
+    ) where
+        ReconcilerErr: std::error::Error + 'static,
+        QueueErr: std::error::Error + 'static,
+    {
+        if let Err(controller::Error::ObjectNotFound(object)) = result {
+            self.dequeue(controller, object.namespace.clone(), object.name.clone());
+        }
+    }
+
+    /// Counts a reconciliation of the given object, its duration is recorded when the returned
+    /// guard is dropped
+    pub fn reconcile_started(&self, controller: &str, object: &impl Resource) -> ReconcileGuard {
+        self.dequeue(controller, object.namespace(), object.name());
+        self.reconciliations.with_label_values(&[controller]).inc();
+        let in_progress = self
+            .reconciliations_in_progress
+            .with_label_values(&[controller]);
+        in_progress.inc();
+        ReconcileGuard {
+            _timer: self
+                .reconcile_duration
+                .with_label_values(&[controller])
+                .start_timer(),
+            in_progress,
+        }
+    }
+
+    pub fn reconcile_failed(&self, controller: &str, category: &str) {
+        self.reconcile_errors
+            .with_label_values(&[controller, category])
+            .inc();
+    }
+
+    /// Counts the outcome of a finished Job, Jobs in progress are ignored
+    pub fn job_finished(&self, controller: &str, job: &str, state: &JobState) {
+        let outcome = match state {
+            JobState::Complete => "complete",
+            JobState::Failed => "failed",
+            JobState::InProgress => return,
+        };
+        self.jobs
+            .with_label_values(&[controller, job, outcome])
+            .inc();
+    }
+
+    /// Encodes all metrics in the Prometheus text format, the object counts are taken from the
+    /// reflector stores of the controllers
+    pub fn encode(
+        &self,
+        superset_dbs: &Store<SupersetDB>,
+        druid_connections: &Store<DruidConnection>,
+    ) -> prometheus::Result<Vec<u8>> {
+        self.superset_dbs.reset();
+        for superset_db in superset_dbs.state() {
+            let condition = superset_db
+                .status
+                .as_ref()
+                .map(|status| format!("{:?}", status.condition))
+                .unwrap_or_else(|| "Unknown".to_string());
+            self.superset_dbs.with_label_values(&[&condition]).inc();
+        }
+        self.druid_connections.reset();
+        for druid_connection in druid_connections.state() {
+            let condition = druid_connection
+                .status
+                .as_ref()
+                .map(|status| format!("{:?}", status.condition))
+                .unwrap_or_else(|| "Unknown".to_string());
+            self.druid_connections
+                .with_label_values(&[&condition])
+                .inc();
+        }
+
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+/// Records the duration of a reconciliation and marks it as finished when dropped
+pub struct ReconcileGuard {
+    _timer: HistogramTimer,
+    in_progress: IntGauge,
+}
+
+impl Drop for ReconcileGuard {
+    fn drop(&mut self) {
+        self.in_progress.dec();
+    }
+}
@@ -0,0 +1,413 @@
+//! Renders the objects which the operator creates for the custom resources in a file without
+//! contacting a cluster, e.g. to review changes of the builders
+//!
+//! Everything which the operator reads from the cluster is left out or replaced: the restart
+//! annotations of the StatefulSets are omitted because they are hashes of Secrets, and the
+//! connection string of Druid is taken from the command line. Objects without a namespace or UID
+//! get a placeholder, the owner references require them.
+
+use crate::{druid_connection_controller, superset_controller, superset_db_controller};
+
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use stackable_operator::{
+    kube::{Resource, ResourceExt},
+    product_config::ProductConfigManager,
+};
+use stackable_superset_crd::{
+    druidconnection::DruidConnection,
+    supersetdb::{InitPhase, SupersetDB},
+    SupersetCluster,
+};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use strum::IntoEnumIterator;
+
+const PLACEHOLDER_NAMESPACE: &str = "default";
+const PLACEHOLDER_UID: &str = "00000000-0000-0000-0000-000000000000";
+
+#[derive(Snafu, Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum Error {
+    #[snafu(display("failed to read {}", path.display()))]
+    ReadFile {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+    #[snafu(display("failed to parse the objects in {}", path.display()))]
+    ParseFile {
+        source: serde_yaml::Error,
+        path: PathBuf,
+    },
+    #[snafu(display(
+        "the kind {kind} is not supported, expected SupersetCluster, SupersetDB or DruidConnection"
+    ))]
+    UnsupportedKind { kind: String },
+    #[snafu(display("{} contains no SupersetCluster", path.display()))]
+    NoSupersetCluster { path: PathBuf },
+    #[snafu(display("the SupersetDB {superset_db} of the DruidConnection {druid_connection} is neither given nor derived from a SupersetCluster"))]
+    NoSupersetDB {
+        superset_db: String,
+        druid_connection: String,
+    },
+    #[snafu(display("invalid credentials"))]
+    InvalidCredentials {
+        source: stackable_superset_crd::Error,
+    },
+    #[snafu(display("failed to derive the SupersetDB from the SupersetCluster"))]
+    DeriveSupersetDB {
+        source: stackable_superset_crd::supersetdb::Error,
+    },
+    #[snafu(display("failed to render the objects of the SupersetCluster"))]
+    RenderSupersetCluster { source: superset_controller::Error },
+    #[snafu(display("failed to render the objects of the SupersetDB"))]
+    RenderSupersetDB {
+        source: superset_db_controller::Error,
+    },
+    #[snafu(display("failed to render the objects of the DruidConnection"))]
+    RenderDruidConnection {
+        source: druid_connection_controller::Error,
+    },
+    #[snafu(display("failed to serialize the rendered objects"))]
+    SerializeObject { source: serde_yaml::Error },
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Returns the rendered objects as YAML documents.
+///
+/// The file must contain at least one `SupersetCluster`. A `SupersetDB` is derived from each
+/// cluster unless it is given in the file as well.
+pub fn render(
+    path: &Path,
+    product_config: &ProductConfigManager,
+    druid_sqlalchemy_uri: Option<&str>,
+) -> Result<String> {
+    let contents = fs::read_to_string(path).context(ReadFileSnafu { path })?;
+    let mut superset_clusters = Vec::new();
+    let mut superset_dbs = Vec::new();
+    let mut druid_connections = Vec::new();
+    for document in serde_yaml::Deserializer::from_str(&contents) {
+        let object = serde_yaml::Value::deserialize(document).context(ParseFileSnafu { path })?;
+        if object.is_null() {
+            continue;
+        }
+        let kind = object
+            .get("kind")
+            .and_then(serde_yaml::Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        match kind.as_str() {
+            "SupersetCluster" => superset_clusters.push(with_placeholders(
+                serde_yaml::from_value::<SupersetCluster>(object)
+                    .context(ParseFileSnafu { path })?,
+            )),
+            "SupersetDB" => superset_dbs.push(with_placeholders(
+                serde_yaml::from_value::<SupersetDB>(object).context(ParseFileSnafu { path })?,
+            )),
+            "DruidConnection" => druid_connections.push(with_placeholders(
+                serde_yaml::from_value::<DruidConnection>(object)
+                    .context(ParseFileSnafu { path })?,
+            )),
+            _ => return UnsupportedKindSnafu { kind }.fail(),
+        }
+    }
+    ensure!(
+        !superset_clusters.is_empty(),
+        NoSupersetClusterSnafu { path }
+    );
+
+    let mut rendered = String::new();
+    for superset in &superset_clusters {
+        if !superset_dbs.iter().any(|superset_db| {
+            superset_db.name() == superset.name() && superset_db.namespace() == superset.namespace()
+        }) {
+            let superset_db = with_placeholders(
+                SupersetDB::for_superset(superset).context(DeriveSupersetDBSnafu)?,
+            );
+            push_object(&mut rendered, &superset_db)?;
+            superset_dbs.push(superset_db);
+        }
+        render_superset_cluster(&mut rendered, superset, product_config)?;
+    }
+    for superset_db in &superset_dbs {
+        render_superset_db(&mut rendered, superset_db)?;
+    }
+    for druid_connection in &druid_connections {
+        let superset = &druid_connection.spec.superset;
+        let superset_db = superset_dbs
+            .iter()
+            .find(|superset_db| {
+                superset_db.name() == superset.name
+                    && superset_db.namespace().as_ref() == Some(&superset.namespace)
+            })
+            .with_context(|| NoSupersetDBSnafu {
+                superset_db: format!("{}/{}", superset.namespace, superset.name),
+                druid_connection: druid_connection.name(),
+            })?;
+        render_druid_connection(
+            &mut rendered,
+            druid_connection,
+            superset_db,
+            druid_sqlalchemy_uri,
+        )?;
+    }
+    Ok(rendered)
+}
+
+fn render_superset_cluster(
+    rendered: &mut String,
+    superset: &SupersetCluster,
+    product_config: &ProductConfigManager,
+) -> Result<()> {
+    let credentials = superset.credentials().context(InvalidCredentialsSnafu)?;
+    // The rolegroups are sorted so that the output can be compared
+    let role_node_config: BTreeMap<_, _> =
+        superset_controller::validated_node_config(superset, product_config)
+            .context(RenderSupersetClusterSnafu)?
+            .into_iter()
+            .collect();
+
+    push_object(
+        rendered,
+        &superset_controller::build_node_role_service(superset)
+            .context(RenderSupersetClusterSnafu)?,
+    )?;
+    for (rolegroup_name, rolegroup_config) in &role_node_config {
+        let rolegroup = superset.node_rolegroup_ref(rolegroup_name);
+        push_object(
+            rendered,
+            &superset_controller::build_node_rolegroup_service(&rolegroup, superset)
+                .context(RenderSupersetClusterSnafu)?,
+        )?;
+        push_object(
+            rendered,
+            &superset_controller::build_rolegroup_config_map(
+                superset,
+                &rolegroup,
+                rolegroup_config,
+                &credentials,
+            )
+            .context(RenderSupersetClusterSnafu)?,
+        )?;
+        push_object(
+            rendered,
+            &superset_controller::build_server_rolegroup_statefulset(
+                &rolegroup,
+                superset,
+                rolegroup_config,
+                &credentials,
+                &BTreeMap::new(),
+            )
+            .context(RenderSupersetClusterSnafu)?,
+        )?;
+    }
+    Ok(())
+}
+
+/// Renders the Jobs of all phases which could run, whether the admin user and the examples are
+/// skipped for an adopted schema is only known in the cluster
+fn render_superset_db(rendered: &mut String, superset_db: &SupersetDB) -> Result<()> {
+    push_object(
+        rendered,
+        &superset_db_controller::build_job_config_map(superset_db)
+            .context(RenderSupersetDBSnafu)?,
+    )?;
+    for phase in InitPhase::iter()
+        .filter(|phase| *phase != InitPhase::Examples || superset_db.spec.load_examples)
+    {
+        push_object(
+            rendered,
+            &superset_db_controller::build_phase_job(superset_db, phase)
+                .context(RenderSupersetDBSnafu)?,
+        )?;
+    }
+    Ok(())
+}
+
+fn render_druid_connection(
+    rendered: &mut String,
+    druid_connection: &DruidConnection,
+    superset_db: &SupersetDB,
+    druid_sqlalchemy_uri: Option<&str>,
+) -> Result<()> {
+    let druid = &druid_connection.spec.druid;
+    let sqlalchemy_uri = druid_sqlalchemy_uri.map(str::to_string).unwrap_or_else(|| {
+        format!(
+            "<DRUID_SQLALCHEMY of the ConfigMap {}/{}>",
+            druid.namespace, druid.name
+        )
+    });
+    push_object(
+        rendered,
+        &druid_connection_controller::build_import_config_map(
+            druid_connection,
+            superset_db,
+            &sqlalchemy_uri,
+        )
+        .context(RenderDruidConnectionSnafu)?,
+    )?;
+    push_object(
+        rendered,
+        &druid_connection_controller::build_import_job(druid_connection, superset_db)
+            .context(RenderDruidConnectionSnafu)?,
+    )?;
+    Ok(())
+}
+
+fn with_placeholders<T: Resource>(mut object: T) -> T {
+    let metadata = object.meta_mut();
+    metadata
+        .namespace
+        .get_or_insert_with(|| PLACEHOLDER_NAMESPACE.to_string());
+    metadata
+        .uid
+        .get_or_insert_with(|| PLACEHOLDER_UID.to_string());
+    object
+}
+
+fn push_object(rendered: &mut String, object: &impl Serialize) -> Result<()> {
+    rendered.push_str(&serde_yaml::to_string(object).context(SerializeObjectSnafu)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OBJECTS: &str = "\
+apiVersion: superset.stackable.tech/v1alpha1
+kind: SupersetCluster
+metadata:
+  name: simple-superset
+spec:
+  version: 1.4.1
+  statsdExporterVersion: v0.22.4
+  credentialsSecret: simple-superset-credentials
+  loadExamplesOnInit: true
+  nodes:
+    roleGroups:
+      default:
+        config:
+          rowLimit: 10000
+---
+apiVersion: superset.stackable.tech/v1alpha1
+kind: DruidConnection
+metadata:
+  name: superset-druid-connection
+spec:
+  superset:
+    name: simple-superset
+    namespace: default
+  druid:
+    name: simple-derby-druid
+    namespace: default
+";
+
+    fn product_config() -> ProductConfigManager {
+        ProductConfigManager::from_yaml_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../deploy/config-spec/properties.yaml"
+        ))
+        .unwrap()
+    }
+
+    /// Renders the objects from a file which is named after the test
+    fn render_objects(
+        test: &str,
+        objects: &str,
+        druid_sqlalchemy_uri: Option<&str>,
+    ) -> Result<String> {
+        let path = std::env::temp_dir().join(format!(
+            "superset-operator-render-{test}-{}.yaml",
+            std::process::id()
+        ));
+        fs::write(&path, objects).unwrap();
+        let rendered = render(&path, &product_config(), druid_sqlalchemy_uri);
+        fs::remove_file(&path).unwrap();
+        rendered
+    }
+
+    fn kinds_and_names(rendered: &str) -> Vec<(String, String)> {
+        serde_yaml::Deserializer::from_str(rendered)
+            .map(|document| {
+                let object = serde_yaml::Value::deserialize(document).unwrap();
+                (
+                    object["kind"].as_str().unwrap().to_string(),
+                    object["metadata"]["name"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn render_lists_the_objects_in_a_stable_order() {
+        let rendered = render_objects(
+            "order",
+            OBJECTS,
+            Some("druid://druid-router:8888/druid/v2/sql"),
+        )
+        .unwrap();
+        let expected = [
+            ("SupersetDB", "simple-superset"),
+            ("Service", "simple-superset-external"),
+            ("Service", "simple-superset-node-default"),
+            ("ConfigMap", "simple-superset-node-default"),
+            ("StatefulSet", "simple-superset-node-default"),
+            ("ConfigMap", "simple-superset-job-config"),
+            ("Job", "simple-superset-migrate"),
+            ("Job", "simple-superset-init"),
+            ("Job", "simple-superset-create-admin"),
+            ("Job", "simple-superset-load-examples"),
+            ("ConfigMap", "superset-druid-connection-import-config"),
+            ("Job", "superset-druid-connection-import"),
+        ]
+        .map(|(kind, name)| (kind.to_string(), name.to_string()));
+        assert_eq!(kinds_and_names(&rendered), expected);
+        assert_eq!(
+            render_objects(
+                "order-again",
+                OBJECTS,
+                Some("druid://druid-router:8888/druid/v2/sql")
+            )
+            .unwrap(),
+            rendered
+        );
+    }
+
+    #[test]
+    fn render_fills_in_the_placeholders() {
+        let rendered = render_objects("placeholders", OBJECTS, None).unwrap();
+        assert!(rendered.contains(&format!("uid: {PLACEHOLDER_UID}")));
+        assert!(rendered.contains("<DRUID_SQLALCHEMY of the ConfigMap default/simple-derby-druid>"));
+        for document in serde_yaml::Deserializer::from_str(&rendered) {
+            let object = serde_yaml::Value::deserialize(document).unwrap();
+            assert_eq!(
+                object["metadata"]["namespace"].as_str(),
+                Some(PLACEHOLDER_NAMESPACE)
+            );
+        }
+    }
+
+    #[test]
+    fn render_requires_a_superset_cluster() {
+        let druid_connection = OBJECTS.split("---\n").nth(1).unwrap();
+        assert!(matches!(
+            render_objects("no-cluster", druid_connection, None),
+            Err(Error::NoSupersetCluster { .. })
+        ));
+    }
+
+    #[test]
+    fn render_rejects_other_kinds() {
+        let objects =
+            format!("{OBJECTS}---\napiVersion: v1\nkind: Secret\nmetadata:\n  name: credentials\n");
+        assert!(matches!(
+            render_objects("other-kinds", &objects, None),
+            Err(Error::UnsupportedKind { kind }) if kind == "Secret"
+        ));
+    }
+}
@@ -0,0 +1,77 @@
+//! HTTP server of the operator which exposes its own metrics and health
+
+use crate::{health::Health, metrics::Metrics};
+
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use stackable_operator::kube::runtime::reflector::Store;
+use stackable_superset_crd::{druidconnection::DruidConnection, supersetdb::SupersetDB};
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+pub struct State {
+    pub health: Arc<Health>,
+    pub metrics: Arc<Metrics>,
+    pub superset_dbs: Store<SupersetDB>,
+    pub druid_connections: Store<DruidConnection>,
+}
+
+pub async fn serve(address: SocketAddr, state: State) -> hyper::Result<()> {
+    let state = Arc::new(state);
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(handle(&request, &state)) }
+            }))
+        }
+    });
+    tracing::info!(%address, "Serving the operator metrics and health");
+    Server::bind(&address).serve(make_service).await
+}
+
+fn handle(request: &Request<Body>, state: &State) -> Response<Body> {
+    match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => {
+            match state
+                .metrics
+                .encode(&state.superset_dbs, &state.druid_connections)
+            {
+                Ok(body) => Response::builder()
+                    .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+                    .body(Body::from(body))
+                    .unwrap(),
+                Err(error) => {
+                    tracing::error!(%error, "Failed to encode the metrics");
+                    response(StatusCode::INTERNAL_SERVER_ERROR)
+                }
+            }
+        }
+        (&Method::GET, "/healthz") => health_response(state.health.liveness()),
+        (&Method::GET, "/readyz") => health_response(state.health.readiness()),
+        _ => response(StatusCode::NOT_FOUND),
+    }
+}
+
+fn health_response((healthy, report): (bool, String)) -> Response<Body> {
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain")
+        .body(Body::from(report))
+        .unwrap()
+}
+
+fn response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
@@ -1,30 +1,55 @@
 //! Ensures that `Pod`s are configured and running for each [`SupersetCluster`]
 
 use crate::{
-    util::{statsd_exporter_version, superset_version},
+    metrics::{Metrics, SUPERSET_CONTROLLER},
+    util::{
+        add_image_pull_settings, add_job_config_volume, add_metadata_database_env,
+        add_metadata_database_tls, add_python_packages, add_superset_config_env, check_credentials,
+        config_snippets_code, config_snippets_hash, config_snippets_volume, credential_hash,
+        get_job_state, job_spec, metadata_database_uri_expression, optional_credential_hash,
+        publish_event, secret_hash, superset_job_config, superset_version, JobState,
+        CONFIG_SNIPPETS_VOLUME, PYTHON_PACKAGES_CONTAINER,
+    },
     APP_NAME, APP_PORT,
 };
 
 use std::{
-    borrow::Cow,
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     sync::Arc,
     time::Duration,
 };
 
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
 use snafu::{OptionExt, ResultExt, Snafu};
 use stackable_operator::{
     builder::{ConfigMapBuilder, ContainerBuilder, ObjectMetaBuilder, PodBuilder},
+    client::Client,
     k8s_openapi::{
         api::{
             apps::v1::{StatefulSet, StatefulSetSpec},
+            batch::v1::Job,
             core::v1::{
-                ConfigMap, ConfigMapVolumeSource, Service, ServicePort, ServiceSpec, Volume,
+                ConfigMap, ConfigMapVolumeSource, EphemeralVolumeSource, HTTPGetAction,
+                PersistentVolumeClaimSpec, PersistentVolumeClaimTemplate, Pod, PodSpec,
+                PodTemplateSpec, Probe, ResourceRequirements, Secret, SecretVolumeSource, Service,
+                ServicePort, ServiceSpec, Volume,
             },
         },
-        apimachinery::pkg::apis::meta::v1::LabelSelector,
+        apimachinery::pkg::{
+            api::resource::Quantity,
+            apis::meta::v1::{LabelSelector, ObjectMeta},
+            util::intstr::IntOrString,
+        },
+    },
+    kube::{
+        api::ListParams,
+        runtime::{
+            controller::{Action, Context},
+            events::EventType,
+            reflector::ObjectRef,
+        },
+        ResourceExt,
     },
-    kube::runtime::controller::{Action, Context},
     labels::{role_group_selector_labels, role_selector_labels},
     logging::controller::ReconcilerError,
     product_config::{
@@ -36,19 +61,59 @@ use stackable_operator::{
     role_utils::RoleGroupRef,
 };
 use stackable_superset_crd::{
-    supersetdb::SupersetDB, SupersetCluster, SupersetConfig, SupersetConfigOptions, SupersetRole,
-    PYTHONPATH, SUPERSET_CONFIG_FILENAME,
+    feature_flags_dict, supersetdb::SupersetDB, GeneratedCredentials, PythonPackagesFailure,
+    ResolvedCredentials, SecretKeyRef, SecretKeyRotationCondition, SecretKeyRotationStatus,
+    SupersetCluster, SupersetClusterStatus, SupersetConfigOptions, SupersetRole, WebserverConfig,
+    WebserverTls, WorkerClass, CONFIG_SNIPPETS_DIR, HTTPS_PORT, KNOWN_FEATURE_FLAGS,
+    MAPBOX_API_KEY_ENV, PYTHONPATH, SUPERSET_CONFIG_FILENAME, TLS_DIR,
 };
 use strum::{EnumDiscriminants, IntoStaticStr};
 
 const FIELD_MANAGER_SCOPE: &str = "supersetcluster";
 
+const SECRET_KEY_HASH_ANNOTATION: &str = "superset.stackable.tech/secret-key-hash";
+const TLS_SECRET_HASH_ANNOTATION: &str = "superset.stackable.tech/tls-secret-hash";
+const CONFIG_SNIPPETS_HASH_ANNOTATION: &str = "superset.stackable.tech/config-snippets-hash";
+
 const METRICS_PORT_NAME: &str = "metrics";
 const METRICS_PORT: i32 = 9102;
 
+/// The file in the rolegroup ConfigMap with the mapping config of the statsd exporter
+const STATSD_MAPPING_FILENAME: &str = "statsd-mapping.yaml";
+const STATSD_MAPPING_DIR: &str = "/stackable/statsd";
+
+/// Maps the metric names of Superset, e.g. `DashboardRestApi.get.time`, to labelled metrics
+const DEFAULT_STATSD_MAPPING: &str = r#"mappings:
+  - match: "*.*.time"
+    name: "superset_duration_seconds"
+    labels:
+      resource: "$1"
+      action: "$2"
+  - match: "*.*.count"
+    name: "superset_calls"
+    labels:
+      resource: "$1"
+      action: "$2"
+  - match: "*.time"
+    name: "superset_action_duration_seconds"
+    labels:
+      action: "$1"
+  - match: "*.count"
+    name: "superset_action_calls"
+    labels:
+      action: "$1"
+  - match: "*.*.*"
+    name: "superset_events"
+    labels:
+      resource: "$1"
+      action: "$2"
+      result: "$3"
+"#;
+
 pub struct Ctx {
     pub client: stackable_operator::client::Client,
     pub product_config: ProductConfigManager,
+    pub metrics: Arc<Metrics>,
 }
 
 #[derive(Snafu, Debug, EnumDiscriminants)]
@@ -57,8 +122,6 @@ pub struct Ctx {
 pub enum Error {
     #[snafu(display("failed to retrieve superset version"))]
     NoSupersetVersion { source: crate::util::Error },
-    #[snafu(display("failed to retrieve statsd exporter version"))]
-    NoStatsdExporterVersion { source: crate::util::Error },
     #[snafu(display("object defines no node role"))]
     NoNodeRole,
     #[snafu(display("failed to calculate global service name"))]
@@ -113,6 +176,64 @@ pub enum Error {
     ObjectMissingMetadataForOwnerRef {
         source: stackable_operator::error::Error,
     },
+    #[snafu(display("invalid credentials"))]
+    InvalidCredentials {
+        source: stackable_superset_crd::Error,
+    },
+    #[snafu(display("failed to check the credentials"))]
+    CredentialsCheck { source: crate::util::Error },
+    #[snafu(display("failed to check whether the credentials Secret {} exists", secret))]
+    CredentialsSecretExistence {
+        source: stackable_operator::error::Error,
+        secret: ObjectRef<Secret>,
+    },
+    #[snafu(display("failed to create the credentials Secret {}", secret))]
+    CreateCredentialsSecret {
+        source: stackable_operator::error::Error,
+        secret: ObjectRef<Secret>,
+    },
+    #[snafu(display("failed to read the secret key"))]
+    ReadSecretKey { source: crate::util::Error },
+    #[snafu(display("failed to read the previous secret key"))]
+    ReadPreviousSecretKey { source: crate::util::Error },
+    #[snafu(display(
+        "failed to apply the ConfigMap of the re-encryption Job {}",
+        config_map
+    ))]
+    ApplyReEncryptionConfigMap {
+        source: stackable_operator::error::Error,
+        config_map: ObjectRef<ConfigMap>,
+    },
+    #[snafu(display("failed to apply re-encryption Job {}", job))]
+    ApplyReEncryptionJob {
+        source: stackable_operator::error::Error,
+        job: ObjectRef<Job>,
+    },
+    #[snafu(display("failed to retrieve re-encryption Job {}", job))]
+    GetReEncryptionJob {
+        source: stackable_operator::error::Error,
+        job: ObjectRef<Job>,
+    },
+    #[snafu(display("failed to update status"))]
+    ApplyStatus {
+        source: stackable_operator::error::Error,
+    },
+    #[snafu(display("failed to read the TLS certificate"))]
+    ReadTlsSecret { source: crate::util::Error },
+    #[snafu(display("invalid config snippets"))]
+    InvalidConfigSnippets {
+        source: stackable_superset_crd::Error,
+    },
+    #[snafu(display("failed to read the config snippets"))]
+    ReadConfigSnippets { source: crate::util::Error },
+    #[snafu(display("failed to list the pods of the cluster"))]
+    ListPods {
+        source: stackable_operator::error::Error,
+    },
+    #[snafu(display("failed to retrieve the current status of the cluster"))]
+    GetSupersetCluster {
+        source: stackable_operator::error::Error,
+    },
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -128,55 +249,125 @@ pub async fn reconcile_superset(
     ctx: Context<Ctx>,
 ) -> Result<Action> {
     tracing::info!("Starting reconcile");
+    let _reconcile_guard = ctx
+        .get_ref()
+        .metrics
+        .reconcile_started(SUPERSET_CONTROLLER, &*superset);
 
     let client = &ctx.get_ref().client;
 
+    create_generated_credentials_secret(client, &superset).await?;
+
+    // The pods must not be started before all credentials are available
+    let ns = superset
+        .namespace()
+        .unwrap_or_else(|| "default".to_string());
+    let credentials = superset.credentials().context(InvalidCredentialsSnafu)?;
+    if let Some(missing) = check_credentials(client, &credentials, &ns)
+        .await
+        .context(CredentialsCheckSnafu)?
+    {
+        // The cluster is reconciled again by the Secret watch when the credentials are added
+        publish_event(
+            client,
+            &*superset,
+            EventType::Warning,
+            "CredentialsMissing",
+            format!("Waiting for the credentials: {missing}"),
+        )
+        .await;
+        return Ok(Action::await_change());
+    }
+
+    // The pods and Jobs use a new secret key only after the stored secrets are re-encrypted
+    let secret_key_hash = credential_hash(client, &credentials.secret_key, &ns)
+        .await
+        .context(ReadSecretKeySnafu)?;
+    let pinned_secret_key = match reencrypt_secrets(
+        client,
+        &ctx.get_ref().metrics,
+        &superset,
+        &credentials,
+        &secret_key_hash,
+    )
+    .await?
+    {
+        EffectiveSecretKey::Current => None,
+        EffectiveSecretKey::Previous { secret_key, hash } => Some((secret_key, hash)),
+        EffectiveSecretKey::Unavailable => return Ok(Action::await_change()),
+    };
+
     // Ensure DB Schema is set up
-    let superset_db = SupersetDB::for_superset(&superset).context(CreateSupersetObjectSnafu)?;
+    let mut superset_db = SupersetDB::for_superset(&superset).context(CreateSupersetObjectSnafu)?;
+    let (credentials, secret_key_hash) = match pinned_secret_key {
+        Some((secret_key, hash)) => {
+            superset_db = superset_db.with_secret_key(secret_key.clone());
+            (
+                ResolvedCredentials {
+                    secret_key,
+                    ..credentials
+                },
+                hash,
+            )
+        }
+        None => (credentials, secret_key_hash),
+    };
     client
         .apply_patch(FIELD_MANAGER_SCOPE, &superset_db, &superset_db)
         .await
         .context(ApplySupersetDBSnafu)?;
 
-    let validated_config = validate_all_roles_and_groups_config(
-        superset_version(&superset).context(NoSupersetVersionSnafu)?,
-        &transform_all_roles_to_config(
-            &*superset,
-            [(
-                SupersetRole::Node.to_string(),
-                (
-                    vec![
-                        PropertyNameKind::Env,
-                        PropertyNameKind::File(SUPERSET_CONFIG_FILENAME.into()),
-                    ],
-                    superset.spec.nodes.clone().context(NoNodeRoleSnafu)?,
-                ),
-            )]
-            .into(),
-        )
-        .context(GenerateProductConfigSnafu)?,
-        &ctx.get_ref().product_config,
-        false,
-        false,
-    )
-    .context(InvalidProductConfigSnafu)?;
-    let role_node_config = validated_config
-        .get(&SupersetRole::Node.to_string())
-        .map(Cow::Borrowed)
-        .unwrap_or_default();
+    // Annotations which roll the pods when the content they are derived from changes
+    let mut restart_annotations =
+        BTreeMap::from([(SECRET_KEY_HASH_ANNOTATION.to_string(), secret_key_hash)]);
+    if let Some(tls_secret_name) = superset.tls_secret_name() {
+        restart_annotations.insert(
+            TLS_SECRET_HASH_ANNOTATION.to_string(),
+            secret_hash(client, tls_secret_name, &ns)
+                .await
+                .context(ReadTlsSecretSnafu)?,
+        );
+    }
+    if !superset.spec.config_snippets.is_empty() {
+        superset
+            .config_snippets()
+            .context(InvalidConfigSnippetsSnafu)?;
+        restart_annotations.insert(
+            CONFIG_SNIPPETS_HASH_ANNOTATION.to_string(),
+            config_snippets_hash(client, &superset.spec.config_snippets, &ns)
+                .await
+                .context(ReadConfigSnippetsSnafu)?,
+        );
+    }
+
+    let role_node_config = validated_node_config(&superset, &ctx.get_ref().product_config)?;
 
     let node_role_service = build_node_role_service(&superset)?;
     client
         .apply_patch(FIELD_MANAGER_SCOPE, &node_role_service, &node_role_service)
         .await
         .context(ApplyRoleServiceSnafu)?;
+    let mut unknown_feature_flags = BTreeSet::new();
     for (rolegroup_name, rolegroup_config) in role_node_config.iter() {
         let rolegroup = superset.node_rolegroup_ref(rolegroup_name);
 
+        unknown_feature_flags.extend(
+            superset
+                .feature_flags(&rolegroup)
+                .into_keys()
+                .filter(|flag| !KNOWN_FEATURE_FLAGS.contains(&flag.as_str())),
+        );
+
         let rg_service = build_node_rolegroup_service(&rolegroup, &superset)?;
-        let rg_configmap = build_rolegroup_config_map(&superset, &rolegroup, rolegroup_config)?;
-        let rg_statefulset =
-            build_server_rolegroup_statefulset(&rolegroup, &superset, rolegroup_config)?;
+        let rg_configmap =
+            build_rolegroup_config_map(&superset, &rolegroup, rolegroup_config, &credentials)?;
+        let rg_statefulset = build_server_rolegroup_statefulset(
+            &rolegroup,
+            &superset,
+            rolegroup_config,
+            &credentials,
+            &restart_annotations,
+        )?;
         client
             .apply_patch(FIELD_MANAGER_SCOPE, &rg_service, &rg_service)
             .await
@@ -197,9 +388,470 @@ pub async fn reconcile_superset(
             })?;
     }
 
+    report_workload_problems(
+        client,
+        &superset,
+        unknown_feature_flags.into_iter().collect(),
+    )
+    .await?;
+
     Ok(Action::await_change())
 }
 
+/// Validates the configuration of the node role against the product config and returns it per
+/// rolegroup
+pub fn validated_node_config(
+    superset: &SupersetCluster,
+    product_config: &ProductConfigManager,
+) -> Result<HashMap<String, HashMap<PropertyNameKind, BTreeMap<String, String>>>> {
+    let mut validated_config = validate_all_roles_and_groups_config(
+        superset_version(superset).context(NoSupersetVersionSnafu)?,
+        &transform_all_roles_to_config(
+            superset,
+            [(
+                SupersetRole::Node.to_string(),
+                (
+                    vec![
+                        PropertyNameKind::Env,
+                        PropertyNameKind::File(SUPERSET_CONFIG_FILENAME.into()),
+                    ],
+                    superset.spec.nodes.clone().context(NoNodeRoleSnafu)?,
+                ),
+            )]
+            .into(),
+        )
+        .context(GenerateProductConfigSnafu)?,
+        product_config,
+        false,
+        false,
+    )
+    .context(InvalidProductConfigSnafu)?;
+    Ok(validated_config
+        .remove(&SupersetRole::Node.to_string())
+        .unwrap_or_default())
+}
+
+/// Records the pods in which the `pythonPackages` could not be installed and the unknown feature
+/// flags in the status
+async fn report_workload_problems(
+    client: &Client,
+    superset: &SupersetCluster,
+    unknown_feature_flags: Vec<String>,
+) -> Result<()> {
+    let ns = superset
+        .namespace()
+        .unwrap_or_else(|| "default".to_string());
+    let failures = if superset.spec.python_packages.is_some() {
+        let pods = client
+            .list::<Pod>(
+                Some(&ns),
+                // Only the pods of the rolegroups, the Jobs report their failures in their details
+                &ListParams::default().labels(
+                    &role_selector_labels(superset, APP_NAME, &SupersetRole::Node.to_string())
+                        .iter()
+                        .map(|(key, value)| format!("{key}={value}"))
+                        .collect::<Vec<_>>()
+                        .join(","),
+                ),
+            )
+            .await
+            .context(ListPodsSnafu)?;
+        pods.iter().filter_map(python_packages_failure).collect()
+    } else {
+        Vec::new()
+    };
+
+    let status = superset.status.clone().unwrap_or_default();
+    if status.unknown_feature_flags != unknown_feature_flags && !unknown_feature_flags.is_empty() {
+        let flags = unknown_feature_flags.join(", ");
+        tracing::warn!(%flags, "Feature flags are unknown to the operator");
+        publish_event(
+            client,
+            superset,
+            EventType::Warning,
+            "UnknownFeatureFlags",
+            format!("The feature flags {flags} are unknown to the operator, check their spelling"),
+        )
+        .await;
+    }
+    if status.python_packages_failures != failures
+        || status.unknown_feature_flags != unknown_feature_flags
+    {
+        // The status may have been changed earlier in this reconciliation
+        let status = client
+            .get::<SupersetCluster>(&superset.name(), Some(&ns))
+            .await
+            .context(GetSupersetClusterSnafu)?
+            .status
+            .unwrap_or_default();
+        let new_status = SupersetClusterStatus {
+            python_packages_failures: failures,
+            unknown_feature_flags,
+            ..status
+        };
+        client
+            .apply_patch_status(FIELD_MANAGER_SCOPE, superset, &new_status)
+            .await
+            .context(ApplyStatusSnafu)?;
+    }
+    Ok(())
+}
+
+/// Returns the failure of the init container which installs the `pythonPackages` unless it
+/// succeeded after a restart
+fn python_packages_failure(pod: &Pod) -> Option<PythonPackagesFailure> {
+    let container_status = pod
+        .status
+        .as_ref()?
+        .init_container_statuses
+        .as_ref()?
+        .iter()
+        .find(|status| status.name == PYTHON_PACKAGES_CONTAINER)?;
+    let current = container_status
+        .state
+        .as_ref()
+        .and_then(|state| state.terminated.as_ref());
+    if current.map(|terminated| terminated.exit_code) == Some(0) {
+        return None;
+    }
+    // A failed init container is restarted, so the failure is usually only kept in the last state
+    let terminated = current
+        .or_else(|| container_status.last_state.as_ref()?.terminated.as_ref())
+        .filter(|terminated| terminated.exit_code != 0)?;
+    Some(PythonPackagesFailure {
+        pod: pod.name(),
+        message: terminated
+            .message
+            .clone()
+            .or_else(|| terminated.reason.clone())
+            .unwrap_or_default(),
+    })
+}
+
+/// The secret key with which the pods and Jobs run
+enum EffectiveSecretKey {
+    /// The stored secrets are encrypted with the current secret key
+    Current,
+    /// The stored secrets are not re-encrypted yet, so the previous key with the given hash is
+    /// used until the re-encryption has succeeded
+    Previous {
+        secret_key: SecretKeyRef,
+        hash: String,
+    },
+    /// The key the stored secrets are encrypted with is not available, the workloads are left as
+    /// they are
+    Unavailable,
+}
+
+/// Re-encrypts the secrets stored in the metadata database if the secret key changed since it was
+/// last recorded in the status, and returns the key which the workloads must use meanwhile.
+async fn reencrypt_secrets(
+    client: &Client,
+    metrics: &Metrics,
+    superset: &SupersetCluster,
+    credentials: &ResolvedCredentials,
+    secret_key_hash: &str,
+) -> Result<EffectiveSecretKey> {
+    let status = superset.status.clone().unwrap_or_default();
+    let stored_key_hash = match &status.secret_key_hash {
+        Some(hash) if hash == secret_key_hash => return Ok(EffectiveSecretKey::Current),
+        Some(hash) => hash.clone(),
+        None => {
+            // Nothing was encrypted by this cluster yet, so the current key is the stored one
+            let new_status = SupersetClusterStatus {
+                secret_key_hash: Some(secret_key_hash.to_string()),
+                ..status
+            };
+            client
+                .apply_patch_status(FIELD_MANAGER_SCOPE, superset, &new_status)
+                .await
+                .context(ApplyStatusSnafu)?;
+            return Ok(EffectiveSecretKey::Current);
+        }
+    };
+
+    let ns = superset
+        .namespace()
+        .unwrap_or_else(|| "default".to_string());
+    let mut rotation = status
+        .secret_key_rotation
+        .clone()
+        .filter(|rotation| rotation.secret_key_hash == secret_key_hash);
+
+    // The previous key is required to re-encrypt the secrets and is used by the workloads until
+    // the re-encryption has succeeded
+    let previous_key_hash = match &credentials.previous_secret_key {
+        Some(previous_secret_key) => optional_credential_hash(client, previous_secret_key, &ns)
+            .await
+            .context(ReadPreviousSecretKeySnafu)?,
+        None => None,
+    };
+    let previous_secret_key = match (&credentials.previous_secret_key, previous_key_hash) {
+        (Some(previous_secret_key), Some(hash)) if hash == stored_key_hash => {
+            previous_secret_key.clone()
+        }
+        (_, previous_key_hash) => {
+            let condition = if previous_key_hash.is_some() {
+                SecretKeyRotationCondition::PreviousKeyMismatch
+            } else {
+                SecretKeyRotationCondition::PreviousKeyMissing
+            };
+            // The status is only written when the condition changes, otherwise every update
+            // would trigger another reconciliation
+            if rotation.map(|rotation| rotation.condition) != Some(condition) {
+                let new_status = SupersetClusterStatus {
+                    secret_key_rotation: Some(SecretKeyRotationStatus::blocked(
+                        secret_key_hash,
+                        condition,
+                    )),
+                    ..status
+                };
+                client
+                    .apply_patch_status(FIELD_MANAGER_SCOPE, superset, &new_status)
+                    .await
+                    .context(ApplyStatusSnafu)?;
+            }
+            return Ok(EffectiveSecretKey::Unavailable);
+        }
+    };
+    let pinned = EffectiveSecretKey::Previous {
+        secret_key: previous_secret_key.clone(),
+        hash: stored_key_hash,
+    };
+
+    let job_name = superset.reencrypt_job_name(secret_key_hash);
+    let job_ref = ObjectRef::<Job>::new(&job_name).within(&ns);
+    match rotation.as_ref().map(|rotation| rotation.condition) {
+        // A failed rotation is retried once its Job is deleted
+        Some(SecretKeyRotationCondition::Failed) => {
+            if !client.exists::<Job>(&job_name, Some(&ns)).await.context(
+                GetReEncryptionJobSnafu {
+                    job: job_ref.clone(),
+                },
+            )? {
+                rotation = None;
+            }
+        }
+        Some(
+            SecretKeyRotationCondition::PreviousKeyMissing
+            | SecretKeyRotationCondition::PreviousKeyMismatch,
+        ) => rotation = None,
+        _ => (),
+    }
+
+    let new_status = match rotation {
+        None => {
+            let config_map = build_reencrypt_config_map(superset, credentials, &job_name)?;
+            client
+                .apply_patch(FIELD_MANAGER_SCOPE, &config_map, &config_map)
+                .await
+                .context(ApplyReEncryptionConfigMapSnafu {
+                    config_map: ObjectRef::<ConfigMap>::new(&job_name).within(&ns),
+                })?;
+            let job = build_reencrypt_job(superset, credentials, &previous_secret_key, &job_name)?;
+            client
+                .apply_patch(FIELD_MANAGER_SCOPE, &job, &job)
+                .await
+                .context(ApplyReEncryptionJobSnafu { job: job_ref })?;
+            tracing::info!("Started re-encryption of the stored secrets with the new secret key");
+            SupersetClusterStatus {
+                secret_key_rotation: Some(SecretKeyRotationStatus::new(secret_key_hash)),
+                ..status
+            }
+        }
+        Some(rotation) if rotation.condition == SecretKeyRotationCondition::ReEncrypting => {
+            let job = client
+                .get::<Job>(&job_name, Some(&ns))
+                .await
+                .context(GetReEncryptionJobSnafu { job: job_ref })?;
+            let job_state = get_job_state(&job);
+            metrics.job_finished(SUPERSET_CONTROLLER, "reencryption", &job_state);
+            match job_state {
+                JobState::Complete => SupersetClusterStatus {
+                    secret_key_hash: Some(secret_key_hash.to_string()),
+                    secret_key_rotation: Some(rotation.succeeded()),
+                    ..status
+                },
+                JobState::Failed => SupersetClusterStatus {
+                    secret_key_rotation: Some(rotation.failed()),
+                    ..status
+                },
+                JobState::InProgress => return Ok(pinned),
+            }
+        }
+        // The workloads keep the previous key until the failed rotation is retried
+        Some(_) => return Ok(pinned),
+    };
+
+    let rotated = new_status.secret_key_hash.as_deref() == Some(secret_key_hash);
+    client
+        .apply_patch_status(FIELD_MANAGER_SCOPE, superset, &new_status)
+        .await
+        .context(ApplyStatusSnafu)?;
+    Ok(if rotated {
+        EffectiveSecretKey::Current
+    } else {
+        pinned
+    })
+}
+
+/// Builds the ConfigMap with the `superset_config.py` of the re-encryption Job, which additionally
+/// defines the previous secret key
+fn build_reencrypt_config_map(
+    superset: &SupersetCluster,
+    credentials: &ResolvedCredentials,
+    job_name: &str,
+) -> Result<ConfigMap> {
+    let config = format!(
+        "{}PREVIOUS_SECRET_KEY = os.environ.get('PREVIOUS_SECRET_KEY')\n",
+        superset
+            .config_snippets()
+            .and_then(|snippets| superset_job_config(credentials, snippets))
+            .context(InvalidConfigSnippetsSnafu)?
+    );
+    Ok(ConfigMap {
+        metadata: ObjectMetaBuilder::new()
+            .name_and_namespace(superset)
+            .name(job_name)
+            .ownerreference_from_resource(superset, None, Some(true))
+            .context(ObjectMissingMetadataForOwnerRefSnafu)?
+            .build(),
+        data: Some([(SUPERSET_CONFIG_FILENAME.to_string(), config)].into()),
+        ..ConfigMap::default()
+    })
+}
+
+/// Builds the Job which decrypts the stored secrets with the previous secret key and encrypts
+/// them with the current one
+fn build_reencrypt_job(
+    superset: &SupersetCluster,
+    credentials: &ResolvedCredentials,
+    previous_secret_key: &SecretKeyRef,
+    job_name: &str,
+) -> Result<Job> {
+    let mut cb = ContainerBuilder::new("superset-reencrypt-secrets");
+    cb.image(
+        superset
+            .product_image()
+            .superset_image(superset_version(superset).context(NoSupersetVersionSnafu)?),
+    )
+    .command(vec!["/bin/bash".to_string()])
+    .args(vec![
+        String::from("-euo"),
+        String::from("pipefail"),
+        String::from("-c"),
+        String::from("superset re-encrypt-secrets"),
+    ])
+    .add_env_var_from_secret(
+        "PREVIOUS_SECRET_KEY",
+        &previous_secret_key.name,
+        &previous_secret_key.key,
+    );
+    add_superset_config_env(&mut cb, credentials);
+
+    let mut pod_spec = PodSpec {
+        containers: vec![cb.build()],
+        restart_policy: Some("Never".to_string()),
+        ..Default::default()
+    };
+    add_job_config_volume(
+        &mut pod_spec,
+        "superset-reencrypt-secrets",
+        job_name,
+        &superset.spec.config_snippets,
+    );
+    add_metadata_database_tls(
+        &mut pod_spec,
+        "superset-reencrypt-secrets",
+        &credentials.metadata_database,
+    );
+    add_image_pull_settings(&mut pod_spec, &superset.product_image());
+    let pod = PodTemplateSpec {
+        metadata: Some(ObjectMetaBuilder::new().name(job_name).build()),
+        spec: Some(pod_spec),
+    };
+
+    Ok(Job {
+        metadata: ObjectMetaBuilder::new()
+            .name_and_namespace(superset)
+            .name(job_name)
+            .ownerreference_from_resource(superset, None, Some(true))
+            .context(ObjectMissingMetadataForOwnerRefSnafu)?
+            .build(),
+        spec: Some(job_spec(pod, superset.spec.database_jobs.as_ref())),
+        status: None,
+    })
+}
+
+/// Creates the credentials Secret if it should be generated and does not exist yet.
+///
+/// An existing Secret is never modified, otherwise the secret key and admin password would change
+/// on every reconciliation.
+async fn create_generated_credentials_secret(
+    client: &Client,
+    superset: &SupersetCluster,
+) -> Result<()> {
+    if let (Some(generated), Some(secret_name)) = (
+        &superset.spec.generate_credentials,
+        superset.credentials_secret_name(),
+    ) {
+        let ns = superset
+            .namespace()
+            .unwrap_or_else(|| "default".to_string());
+        let secret_ref = ObjectRef::<Secret>::new(&secret_name).within(&ns);
+        if !client
+            .exists::<Secret>(&secret_name, Some(&ns))
+            .await
+            .context(CredentialsSecretExistenceSnafu {
+                secret: secret_ref.clone(),
+            })?
+        {
+            let secret = build_generated_credentials_secret(superset, &secret_name, generated)?;
+            client
+                .create(&secret)
+                .await
+                .context(CreateCredentialsSecretSnafu { secret: secret_ref })?;
+            tracing::info!("Created credentials Secret {}", secret_name);
+        }
+    }
+
+    Ok(())
+}
+
+/// The generated credentials [`Secret`] is owned by the cluster and deleted together with it
+fn build_generated_credentials_secret(
+    superset: &SupersetCluster,
+    secret_name: &str,
+    generated: &GeneratedCredentials,
+) -> Result<Secret> {
+    Ok(Secret {
+        metadata: ObjectMetaBuilder::new()
+            .name_and_namespace(superset)
+            .name(secret_name)
+            .ownerreference_from_resource(superset, None, Some(true))
+            .context(ObjectMissingMetadataForOwnerRefSnafu)?
+            .with_recommended_labels(
+                superset,
+                APP_NAME,
+                superset_version(superset).context(NoSupersetVersionSnafu)?,
+                "",
+                "",
+            )
+            .build(),
+        string_data: Some(generated.secret_data(random_string(64), random_string(32))),
+        ..Secret::default()
+    })
+}
+
+/// Returns a random alphanumeric string from the random number generator of the operating system
+fn random_string(len: usize) -> String {
+    OsRng
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
 /// The server-role service is the primary endpoint that should be used by clients that do not perform internal load balancing,
 /// including targets outside of the cluster.
 pub fn build_node_role_service(superset: &SupersetCluster) -> Result<Service> {
@@ -220,14 +872,11 @@ pub fn build_node_role_service(superset: &SupersetCluster) -> Result<Service> {
                 &role_name,
                 "global",
             )
-            .with_label(
-                "statsd-exporter",
-                statsd_exporter_version(superset).context(NoStatsdExporterVersionSnafu)?,
-            )
+            .with_labels(statsd_exporter_labels(superset))
             .build(),
         spec: Some(ServiceSpec {
             ports: Some(vec![ServicePort {
-                name: Some("superset".to_string()),
+                name: Some(service_port_name(superset).to_string()),
                 port: APP_PORT.into(),
                 protocol: Some("TCP".to_string()),
                 ..ServicePort::default()
@@ -241,10 +890,11 @@ pub fn build_node_role_service(superset: &SupersetCluster) -> Result<Service> {
 }
 
 /// The rolegroup [`ConfigMap`] configures the rolegroup based on the configuration given by the administrator
-fn build_rolegroup_config_map(
+pub fn build_rolegroup_config_map(
     superset: &SupersetCluster,
     rolegroup: &RoleGroupRef<SupersetCluster>,
     rolegroup_config: &HashMap<PropertyNameKind, BTreeMap<String, String>>,
+    credentials: &ResolvedCredentials,
 ) -> Result<ConfigMap, Error> {
     let mut config = rolegroup_config
         .get(&PropertyNameKind::File(
@@ -259,15 +909,22 @@ fn build_rolegroup_config_map(
     );
     config.insert(
         SupersetConfigOptions::SqlalchemyDatabaseUri.to_string(),
-        "os.environ.get('SQLALCHEMY_DATABASE_URI')".into(),
-    );
-    config.insert(
-        SupersetConfigOptions::StatsLogger.to_string(),
-        "StatsdStatsLogger(host='0.0.0.0', port=9125)".into(),
+        metadata_database_uri_expression(&credentials.metadata_database, "SQLALCHEMY_DATABASE_URI"),
     );
+    if let Some(stats_logger) = superset.stats_logger() {
+        config.insert(SupersetConfigOptions::StatsLogger.to_string(), stats_logger);
+    }
+    // An overridden FEATURE_FLAGS expression takes precedence over the typed flags
+    let feature_flags = superset.feature_flags(rolegroup);
+    if !feature_flags.is_empty() {
+        config
+            .entry(SupersetConfigOptions::FeatureFlags.to_string())
+            .or_insert_with(|| feature_flags_dict(&feature_flags));
+    }
 
     let imports = [
         "import os",
+        "from urllib.parse import quote",
         "from superset.stats_logger import StatsdStatsLogger",
     ];
 
@@ -280,8 +937,27 @@ fn build_rolegroup_config_map(
     .with_context(|_| BuildRoleGroupConfigFileSnafu {
         rolegroup: rolegroup.clone(),
     })?;
+    if !superset.spec.config_snippets.is_empty() {
+        config_file.extend(
+            config_snippets_code(&superset.spec.config_snippets)
+                .context(InvalidConfigSnippetsSnafu)?
+                .as_bytes(),
+        );
+    }
 
-    ConfigMapBuilder::new()
+    let mut config_map_builder = ConfigMapBuilder::new();
+    if superset.spec.statsd_exporter_version.is_some() {
+        config_map_builder.add_data(
+            STATSD_MAPPING_FILENAME,
+            superset
+                .spec
+                .statsd
+                .as_ref()
+                .and_then(|statsd| statsd.mapping_config.clone())
+                .unwrap_or_else(|| DEFAULT_STATSD_MAPPING.to_string()),
+        );
+    }
+    config_map_builder
         .metadata(
             ObjectMetaBuilder::new()
                 .name_and_namespace(superset)
@@ -307,13 +983,50 @@ fn build_rolegroup_config_map(
         })
 }
 
+/// The name of the webserver port of the Services. It stays `superset` for plain HTTP so that
+/// existing references to the port keep working.
+fn service_port_name(superset: &SupersetCluster) -> &'static str {
+    if superset.spec.tls.is_some() {
+        HTTPS_PORT
+    } else {
+        "superset"
+    }
+}
+
+/// The label with the version of the statsd exporter sidecar, if it is deployed
+fn statsd_exporter_labels(superset: &SupersetCluster) -> BTreeMap<String, String> {
+    superset
+        .spec
+        .statsd_exporter_version
+        .iter()
+        .map(|version| ("statsd-exporter".to_string(), version.clone()))
+        .collect()
+}
+
 /// The rolegroup [`Service`] is a headless service that allows direct access to the instances of a certain rolegroup
 ///
 /// This is mostly useful for internal communication between peers, or for clients that perform client-side load balancing.
-fn build_node_rolegroup_service(
+pub fn build_node_rolegroup_service(
     rolegroup: &RoleGroupRef<SupersetCluster>,
     superset: &SupersetCluster,
 ) -> Result<Service> {
+    let mut ports = vec![ServicePort {
+        name: Some(service_port_name(superset).to_string()),
+        port: APP_PORT.into(),
+        protocol: Some("TCP".to_string()),
+        ..ServicePort::default()
+    }];
+    let mut scrape_labels = statsd_exporter_labels(superset);
+    if superset.spec.statsd_exporter_version.is_some() {
+        ports.push(ServicePort {
+            name: Some(METRICS_PORT_NAME.into()),
+            port: METRICS_PORT,
+            protocol: Some("TCP".to_string()),
+            ..ServicePort::default()
+        });
+        scrape_labels.insert("prometheus.io/scrape".to_string(), "true".to_string());
+    }
+
     Ok(Service {
         metadata: ObjectMetaBuilder::new()
             .name_and_namespace(superset)
@@ -327,28 +1040,11 @@ fn build_node_rolegroup_service(
                 &rolegroup.role,
                 &rolegroup.role_group,
             )
-            .with_label(
-                "statsd-exporter",
-                statsd_exporter_version(superset).context(NoStatsdExporterVersionSnafu)?,
-            )
-            .with_label("prometheus.io/scrape", "true")
+            .with_labels(scrape_labels)
             .build(),
         spec: Some(ServiceSpec {
             cluster_ip: Some("None".to_string()),
-            ports: Some(vec![
-                ServicePort {
-                    name: Some("superset".to_string()),
-                    port: APP_PORT.into(),
-                    protocol: Some("TCP".to_string()),
-                    ..ServicePort::default()
-                },
-                ServicePort {
-                    name: Some(METRICS_PORT_NAME.into()),
-                    port: METRICS_PORT,
-                    protocol: Some("TCP".to_string()),
-                    ..ServicePort::default()
-                },
-            ]),
+            ports: Some(ports),
             selector: Some(role_group_selector_labels(
                 superset,
                 APP_NAME,
@@ -365,10 +1061,12 @@ fn build_node_rolegroup_service(
 /// The rolegroup [`StatefulSet`] runs the rolegroup, as configured by the administrator.
 ///
 /// The [`Pod`](`stackable_operator::k8s_openapi::api::core::v1::Pod`)s are accessible through the corresponding [`Service`] (from [`build_node_rolegroup_service`]).
-fn build_server_rolegroup_statefulset(
+pub fn build_server_rolegroup_statefulset(
     rolegroup_ref: &RoleGroupRef<SupersetCluster>,
     superset: &SupersetCluster,
     node_config: &HashMap<PropertyNameKind, BTreeMap<String, String>>,
+    credentials: &ResolvedCredentials,
+    restart_annotations: &BTreeMap<String, String>,
 ) -> Result<StatefulSet> {
     let rolegroup = superset
         .spec
@@ -380,13 +1078,8 @@ fn build_server_rolegroup_statefulset(
 
     let superset_version = superset_version(superset).context(NoSupersetVersionSnafu)?;
 
-    let image = format!("docker.stackable.tech/stackable/superset:{superset_version}-stackable1");
-
-    let statsd_exporter_version =
-        statsd_exporter_version(superset).context(NoStatsdExporterVersionSnafu)?;
-
-    let statsd_exporter_image =
-        format!("docker.stackable.tech/prom/statsd-exporter:{statsd_exporter_version}");
+    let product_image = superset.product_image();
+    let image = product_image.superset_image(superset_version);
 
     let mut cb = ContainerBuilder::new("superset");
 
@@ -395,27 +1088,129 @@ fn build_server_rolegroup_statefulset(
         .cloned()
         .unwrap_or_default()
     {
-        if name == SupersetConfig::CREDENTIALS_SECRET_PROPERTY {
-            cb.add_env_var_from_secret("SECRET_KEY", &value, "connections.secretKey");
-            cb.add_env_var_from_secret(
-                "SQLALCHEMY_DATABASE_URI",
-                &value,
-                "connections.sqlalchemyDatabaseUri",
-            );
-        } else {
-            cb.add_env_var(name, value);
-        };
+        cb.add_env_var(name, value);
+    }
+    cb.add_env_var_from_secret(
+        "SECRET_KEY",
+        &credentials.secret_key.name,
+        &credentials.secret_key.key,
+    );
+    add_metadata_database_env(
+        &mut cb,
+        &credentials.metadata_database,
+        "SQLALCHEMY_DATABASE_URI",
+    );
+    if let Some(mapbox_api_key) = superset.mapbox_api_key(rolegroup_ref) {
+        cb.add_env_var_from_secret(
+            MAPBOX_API_KEY_ENV,
+            &mapbox_api_key.name,
+            &mapbox_api_key.key,
+        );
+    }
+
+    if !superset.spec.config_snippets.is_empty() {
+        cb.add_volume_mount(CONFIG_SNIPPETS_VOLUME, CONFIG_SNIPPETS_DIR);
     }
 
-    let container = cb
+    if superset.spec.tls.is_some() {
+        cb.add_volume_mount("tls", TLS_DIR);
+    }
+
+    let mut container = cb
         .image(image)
-        .add_container_port("http", APP_PORT.into())
+        .command(vec!["gunicorn".to_string()])
+        .args(gunicorn_args(
+            superset,
+            &superset.webserver_config(rolegroup_ref),
+        ))
+        .add_container_port(superset.webserver_port_name(), APP_PORT.into())
         .add_volume_mount("config", PYTHONPATH)
         .build();
-    let metrics_container = ContainerBuilder::new("metrics")
-        .image(statsd_exporter_image)
-        .add_container_port(METRICS_PORT_NAME, METRICS_PORT)
-        .build();
+    let health_check = HTTPGetAction {
+        path: Some("/health".to_string()),
+        port: IntOrString::String(superset.webserver_port_name().to_string()),
+        scheme: Some(
+            if superset.spec.tls.is_some() {
+                "HTTPS"
+            } else {
+                "HTTP"
+            }
+            .to_string(),
+        ),
+        ..HTTPGetAction::default()
+    };
+    container.readiness_probe = Some(Probe {
+        http_get: Some(health_check.clone()),
+        initial_delay_seconds: Some(10),
+        period_seconds: Some(10),
+        ..Probe::default()
+    });
+    container.liveness_probe = Some(Probe {
+        http_get: Some(health_check),
+        // Superset takes a while to start
+        initial_delay_seconds: Some(60),
+        period_seconds: Some(10),
+        failure_threshold: Some(6),
+        ..Probe::default()
+    });
+    let mut pod_builder = PodBuilder::new();
+    pod_builder
+        .metadata_builder(|m| {
+            m.with_recommended_labels(
+                superset,
+                APP_NAME,
+                superset_version,
+                &rolegroup_ref.role,
+                &rolegroup_ref.role_group,
+            )
+            .with_labels(statsd_exporter_labels(superset));
+            for (key, value) in restart_annotations {
+                m.with_annotation(key, value);
+            }
+            m
+        })
+        .add_container(container)
+        .add_volume(Volume {
+            name: "config".to_string(),
+            config_map: Some(ConfigMapVolumeSource {
+                name: Some(rolegroup_ref.object_name()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    if let Some(statsd_exporter_version) = &superset.spec.statsd_exporter_version {
+        let statsd_port = superset.statsd_port();
+        pod_builder.add_container(
+            ContainerBuilder::new("metrics")
+                .image(product_image.statsd_exporter_image(statsd_exporter_version))
+                .args(vec![
+                    format!(
+                        "--statsd.mapping-config={STATSD_MAPPING_DIR}/{STATSD_MAPPING_FILENAME}"
+                    ),
+                    format!("--statsd.listen-udp=:{statsd_port}"),
+                    format!("--statsd.listen-tcp=:{statsd_port}"),
+                    format!("--web.listen-address=:{METRICS_PORT}"),
+                ])
+                .add_container_port(METRICS_PORT_NAME, METRICS_PORT)
+                .add_volume_mount("config", STATSD_MAPPING_DIR)
+                .build(),
+        );
+    }
+    if let Some(tls_volume) = tls_volume(superset) {
+        pod_builder.add_volume(tls_volume);
+    }
+    if !superset.spec.config_snippets.is_empty() {
+        pod_builder.add_volume(config_snippets_volume(&superset.spec.config_snippets));
+    }
+    let mut pod_template = pod_builder.build_template();
+    if let Some(pod_spec) = &mut pod_template.spec {
+        add_metadata_database_tls(pod_spec, "superset", &credentials.metadata_database);
+        if let Some(python_packages) = &superset.spec.python_packages {
+            add_python_packages(pod_spec, "superset", python_packages);
+        }
+        add_image_pull_settings(pod_spec, &product_image);
+    }
+
     Ok(StatefulSet {
         metadata: ObjectMetaBuilder::new()
             .name_and_namespace(superset)
@@ -429,7 +1224,7 @@ fn build_server_rolegroup_statefulset(
                 &rolegroup_ref.role,
                 &rolegroup_ref.role_group,
             )
-            .with_label("statsd-exporter", statsd_exporter_version)
+            .with_labels(statsd_exporter_labels(superset))
             .build(),
         spec: Some(StatefulSetSpec {
             pod_management_policy: Some("Parallel".to_string()),
@@ -448,34 +1243,117 @@ fn build_server_rolegroup_statefulset(
                 ..LabelSelector::default()
             },
             service_name: rolegroup_ref.object_name(),
-            template: PodBuilder::new()
-                .metadata_builder(|m| {
-                    m.with_recommended_labels(
-                        superset,
-                        APP_NAME,
-                        superset_version,
-                        &rolegroup_ref.role,
-                        &rolegroup_ref.role_group,
-                    )
-                    .with_label("statsd-exporter", statsd_exporter_version)
-                })
-                .add_container(container)
-                .add_container(metrics_container)
-                .add_volume(Volume {
-                    name: "config".to_string(),
-                    config_map: Some(ConfigMapVolumeSource {
-                        name: Some(rolegroup_ref.object_name()),
-                        ..Default::default()
-                    }),
-                    ..Default::default()
-                })
-                .build_template(),
+            template: pod_template,
             ..StatefulSetSpec::default()
         }),
         status: None,
     })
 }
 
-pub fn error_policy(_error: &Error, _ctx: Context<Ctx>) -> Action {
+/// The command line arguments of Gunicorn, which runs the webserver instead of the entrypoint of
+/// the image
+fn gunicorn_args(superset: &SupersetCluster, config: &WebserverConfig) -> Vec<String> {
+    let mut args = vec![
+        format!("--bind=0.0.0.0:{APP_PORT}"),
+        "--access-logfile=-".to_string(),
+        "--error-logfile=-".to_string(),
+        format!(
+            "--workers={}",
+            config.workers.unwrap_or(WebserverConfig::DEFAULT_WORKERS)
+        ),
+        format!(
+            "--worker-class={}",
+            config.worker_class.unwrap_or(WorkerClass::Gthread)
+        ),
+        format!(
+            "--threads={}",
+            config.threads.unwrap_or(WebserverConfig::DEFAULT_THREADS)
+        ),
+        format!(
+            "--timeout={}",
+            config.timeout.unwrap_or(WebserverConfig::DEFAULT_TIMEOUT)
+        ),
+        format!(
+            "--keep-alive={}",
+            config
+                .keepalive
+                .unwrap_or(WebserverConfig::DEFAULT_KEEPALIVE)
+        ),
+        format!(
+            "--limit-request-line={}",
+            config
+                .limit_request_line
+                .unwrap_or(WebserverConfig::DEFAULT_LIMIT_REQUEST_LINE)
+        ),
+        format!(
+            "--limit-request-field_size={}",
+            config
+                .limit_request_field_size
+                .unwrap_or(WebserverConfig::DEFAULT_LIMIT_REQUEST_FIELD_SIZE)
+        ),
+    ];
+    if superset.spec.tls.is_some() {
+        args.push(format!("--certfile={TLS_DIR}/tls.crt"));
+        args.push(format!("--keyfile={TLS_DIR}/tls.key"));
+    }
+    args.push("superset.app:create_app()".to_string());
+    args
+}
+
+/// The volume with the webserver certificate, mounted at [`TLS_DIR`]
+fn tls_volume(superset: &SupersetCluster) -> Option<Volume> {
+    let volume = match superset.spec.tls.as_ref()? {
+        WebserverTls::Secret { name } => Volume {
+            name: "tls".to_string(),
+            secret: Some(SecretVolumeSource {
+                secret_name: Some(name.clone()),
+                ..SecretVolumeSource::default()
+            }),
+            ..Volume::default()
+        },
+        WebserverTls::SecretClass(secret_class) => Volume {
+            name: "tls".to_string(),
+            ephemeral: Some(EphemeralVolumeSource {
+                volume_claim_template: Some(PersistentVolumeClaimTemplate {
+                    metadata: Some(ObjectMeta {
+                        annotations: Some(BTreeMap::from([
+                            (
+                                "secrets.stackable.tech/class".to_string(),
+                                secret_class.clone(),
+                            ),
+                            (
+                                "secrets.stackable.tech/scope".to_string(),
+                                format!(
+                                    "node,pod,service={}-external",
+                                    superset.node_role_service_name().unwrap_or_default()
+                                ),
+                            ),
+                        ])),
+                        ..ObjectMeta::default()
+                    }),
+                    spec: PersistentVolumeClaimSpec {
+                        access_modes: Some(vec!["ReadWriteOnce".to_string()]),
+                        resources: Some(ResourceRequirements {
+                            requests: Some(BTreeMap::from([(
+                                "storage".to_string(),
+                                Quantity("1".to_string()),
+                            )])),
+                            ..ResourceRequirements::default()
+                        }),
+                        storage_class_name: Some("secrets.stackable.tech".to_string()),
+                        ..PersistentVolumeClaimSpec::default()
+                    },
+                }),
+            }),
+            ..Volume::default()
+        },
+    };
+    Some(volume)
+}
+
+pub fn error_policy(error: &Error, ctx: Context<Ctx>) -> Action {
+    ctx.get_ref()
+        .metrics
+        .reconcile_failed(SUPERSET_CONTROLLER, error.category());
     Action::requeue(Duration::from_secs(5))
 }
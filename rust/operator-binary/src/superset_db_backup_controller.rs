@@ -0,0 +1,291 @@
+//! Dumps the metadata database of a [`SupersetDB`] into a volume for each [`SupersetDBBackup`]
+
+use crate::metrics::{Metrics, SUPERSET_DB_BACKUP_CONTROLLER};
+use crate::util::{
+    add_image_pull_settings, add_metadata_database_env, add_metadata_database_tls,
+    database_client_preamble, get_job_state, job_spec, metadata_database_uri_commands,
+    publish_event, JobState,
+};
+
+use snafu::{ResultExt, Snafu};
+use stackable_operator::{
+    builder::{ContainerBuilder, ObjectMetaBuilder},
+    k8s_openapi::api::{
+        batch::v1::Job,
+        core::v1::{PersistentVolumeClaimVolumeSource, PodSpec, PodTemplateSpec, Volume},
+    },
+    kube::{
+        api::Api,
+        runtime::{
+            controller::{Action, Context},
+            events::EventType,
+            reflector::ObjectRef,
+        },
+        ResourceExt,
+    },
+    logging::controller::ReconcilerError,
+};
+use stackable_superset_crd::{
+    supersetdb::SupersetDB,
+    supersetdbbackup::{
+        SupersetDBBackup, SupersetDBBackupStatus, SupersetDBBackupStatusCondition, BACKUP_DIR,
+    },
+    DatabaseType,
+};
+use std::{sync::Arc, time::Duration};
+use strum::{EnumDiscriminants, IntoStaticStr};
+
+const FIELD_MANAGER_SCOPE: &str = "supersetcluster";
+
+pub struct Ctx {
+    pub client: stackable_operator::client::Client,
+    pub metrics: Arc<Metrics>,
+}
+
+#[derive(Snafu, Debug, EnumDiscriminants)]
+#[strum_discriminants(derive(IntoStaticStr))]
+#[allow(clippy::enum_variant_names)]
+pub enum Error {
+    #[snafu(display("failed to apply Job for {}", backup))]
+    ApplyJob {
+        source: stackable_operator::error::Error,
+        backup: ObjectRef<SupersetDBBackup>,
+    },
+    #[snafu(display("failed to update status"))]
+    ApplyStatus {
+        source: stackable_operator::error::Error,
+    },
+    #[snafu(display("object is missing metadata to build owner reference"))]
+    ObjectMissingMetadataForOwnerRef {
+        source: stackable_operator::error::Error,
+    },
+    #[snafu(display("invalid credentials"))]
+    InvalidCredentials {
+        source: stackable_superset_crd::Error,
+    },
+    #[snafu(display("failed to retrieve superset db {}", superset_db))]
+    SupersetDBRetrieval {
+        source: stackable_operator::error::Error,
+        superset_db: ObjectRef<SupersetDB>,
+    },
+    #[snafu(display("backup state is 'running' but failed to find job {}", backup_job))]
+    GetBackupJob {
+        source: stackable_operator::kube::Error,
+        backup_job: ObjectRef<Job>,
+    },
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl ReconcilerError for Error {
+    fn category(&self) -> &'static str {
+        ErrorDiscriminants::from(self).into()
+    }
+}
+
+pub async fn reconcile_superset_db_backup(
+    backup: Arc<SupersetDBBackup>,
+    ctx: Context<Ctx>,
+) -> Result<Action> {
+    tracing::info!("Starting reconcile");
+    let _reconcile_guard = ctx
+        .get_ref()
+        .metrics
+        .reconcile_started(SUPERSET_DB_BACKUP_CONTROLLER, &*backup);
+
+    let client = &ctx.get_ref().client;
+    let ns = backup.namespace().unwrap_or_else(|| "default".to_string());
+
+    if let Some(ref s) = backup.status {
+        match s.condition {
+            SupersetDBBackupStatusCondition::Pending => {
+                let superset_db = client
+                    .get::<SupersetDB>(&backup.spec.superset_db, Some(&ns))
+                    .await
+                    .context(SupersetDBRetrievalSnafu {
+                        superset_db: ObjectRef::<SupersetDB>::new(&backup.spec.superset_db)
+                            .within(&ns),
+                    })?;
+                // The dump contains the schema of the last initialization or migration, which
+                // differs from the desired version if the backup precedes a migration
+                let superset_version = superset_db
+                    .status
+                    .as_ref()
+                    .and_then(|status| status.superset_version.clone())
+                    .unwrap_or_else(|| superset_db.spec.superset_version.clone());
+
+                let job = build_backup_job(&backup, &superset_db)?;
+                client
+                    .apply_patch(FIELD_MANAGER_SCOPE, &job, &job)
+                    .await
+                    .context(ApplyJobSnafu {
+                        backup: ObjectRef::from_obj(&*backup),
+                    })?;
+                // The job is started, update status to reflect new state
+                client
+                    .apply_patch_status(
+                        FIELD_MANAGER_SCOPE,
+                        &*backup,
+                        &s.running(&superset_version),
+                    )
+                    .await
+                    .context(ApplyStatusSnafu)?;
+            }
+            SupersetDBBackupStatusCondition::Running => {
+                let job_name = backup.job_name();
+                let job = match Api::<Job>::namespaced(client.as_kube_client(), &ns)
+                    .get(&job_name)
+                    .await
+                {
+                    Ok(job) => job,
+                    Err(stackable_operator::kube::Error::Api(response)) if response.code == 404 => {
+                        // The Job was deleted before its outcome was recorded, e.g. because it
+                        // was garbage-collected while the operator was not running. The backup
+                        // is started over.
+                        publish_event(
+                            client,
+                            &*backup,
+                            EventType::Warning,
+                            "JobNotFound",
+                            format!(
+                                "The backup Job {job_name} was deleted before it was observed as \
+                                 finished, the backup is started again"
+                            ),
+                        )
+                        .await;
+                        client
+                            .apply_patch_status(
+                                FIELD_MANAGER_SCOPE,
+                                &*backup,
+                                &SupersetDBBackupStatus::new(),
+                            )
+                            .await
+                            .context(ApplyStatusSnafu)?;
+                        return Ok(Action::await_change());
+                    }
+                    Err(source) => {
+                        return Err(Error::GetBackupJob {
+                            source,
+                            backup_job: ObjectRef::<Job>::new(&job_name).within(&ns),
+                        })
+                    }
+                };
+
+                let job_state = get_job_state(&job);
+                ctx.get_ref().metrics.job_finished(
+                    SUPERSET_DB_BACKUP_CONTROLLER,
+                    "backup",
+                    &job_state,
+                );
+                let new_status = match job_state {
+                    JobState::Complete => Some(s.succeeded()),
+                    JobState::Failed => Some(s.failed()),
+                    JobState::InProgress => None,
+                };
+
+                if let Some(ns) = new_status {
+                    client
+                        .apply_patch_status(FIELD_MANAGER_SCOPE, &*backup, &ns)
+                        .await
+                        .context(ApplyStatusSnafu)?;
+                }
+            }
+            SupersetDBBackupStatusCondition::Succeeded => (),
+            SupersetDBBackupStatusCondition::Failed => (),
+        }
+    } else {
+        client
+            .apply_patch_status(
+                FIELD_MANAGER_SCOPE,
+                &*backup,
+                &SupersetDBBackupStatus::new(),
+            )
+            .await
+            .context(ApplyStatusSnafu)?;
+    }
+
+    Ok(Action::await_change())
+}
+
+/// Builds the backup job. When run it will dump the metadata database into the backup volume.
+fn build_backup_job(backup: &SupersetDBBackup, superset_db: &SupersetDB) -> Result<Job> {
+    let db_type = backup.spec.dump.db_type;
+    let dump_file = backup.dump_file();
+    let credentials = superset_db.credentials().context(InvalidCredentialsSnafu)?;
+
+    let mut commands = metadata_database_uri_commands(&credentials.metadata_database);
+    commands.extend(database_client_preamble(
+        db_type,
+        &credentials.metadata_database,
+    ));
+    commands.push(match db_type {
+        DatabaseType::Postgresql => {
+            format!("pg_dump --dbname=\"$uri\" --format=custom --file={dump_file}")
+        }
+        DatabaseType::Mysql => format!(
+            "mysqldump \"${{mysql_args[@]}}\" --single-transaction --routines \"$database\" > {dump_file}"
+        ),
+    });
+
+    let mut cb = ContainerBuilder::new("superset-db-backup");
+    cb.image(backup.spec.dump.client_image(&superset_db.product_image()))
+        .command(vec!["/bin/bash".to_string()])
+        .args(vec![
+            String::from("-euo"),
+            String::from("pipefail"),
+            String::from("-c"),
+            commands.join("\n"),
+        ])
+        .add_volume_mount("backup", BACKUP_DIR);
+    add_metadata_database_env(&mut cb, &credentials.metadata_database, "DATABASE_URI");
+    let container = cb.build();
+
+    let mut pod_spec = PodSpec {
+        containers: vec![container],
+        restart_policy: Some("Never".to_string()),
+        volumes: Some(vec![backup_volume(&backup.spec.dump.claim_name)]),
+        ..Default::default()
+    };
+    add_metadata_database_tls(
+        &mut pod_spec,
+        "superset-db-backup",
+        &credentials.metadata_database,
+    );
+    add_image_pull_settings(&mut pod_spec, &superset_db.product_image());
+    let pod = PodTemplateSpec {
+        metadata: Some(ObjectMetaBuilder::new().name(backup.job_name()).build()),
+        spec: Some(pod_spec),
+    };
+
+    let job = Job {
+        metadata: ObjectMetaBuilder::new()
+            .name(backup.job_name())
+            .namespace_opt(backup.namespace())
+            .ownerreference_from_resource(backup, None, Some(true))
+            .context(ObjectMissingMetadataForOwnerRefSnafu)?
+            .build(),
+        spec: Some(job_spec(pod, backup.spec.job.as_ref())),
+        status: None,
+    };
+
+    Ok(job)
+}
+
+/// The volume which holds the dumps, mounted at [`BACKUP_DIR`]
+pub fn backup_volume(claim_name: &str) -> Volume {
+    Volume {
+        name: "backup".to_string(),
+        persistent_volume_claim: Some(PersistentVolumeClaimVolumeSource {
+            claim_name: claim_name.to_string(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+pub fn error_policy(error: &Error, ctx: Context<Ctx>) -> Action {
+    ctx.get_ref()
+        .metrics
+        .reconcile_failed(SUPERSET_DB_BACKUP_CONTROLLER, error.category());
+    Action::requeue(Duration::from_secs(5))
+}
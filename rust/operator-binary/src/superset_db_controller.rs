@@ -1,15 +1,23 @@
-use crate::util::{get_job_state, JobState};
+use crate::metrics::{Metrics, SUPERSET_DB_CONTROLLER};
+use crate::util::{
+    add_image_pull_settings, add_job_config_volume, add_metadata_database_tls, add_python_packages,
+    add_superset_config_env, check_credentials, get_job_state, job_details, job_failure_note,
+    job_spec, publish_event, superset_job_config, with_failure_summary, JobState,
+};
 
+use serde_json::json;
 use snafu::{ResultExt, Snafu};
 use stackable_operator::{
     builder::{ContainerBuilder, ObjectMetaBuilder},
     k8s_openapi::api::{
-        batch::v1::{Job, JobSpec},
-        core::v1::{PodSpec, PodTemplateSpec, Secret},
+        batch::v1::Job,
+        core::v1::{ConfigMap, Container, PodSpec, PodTemplateSpec},
     },
     kube::{
+        api::{Api, DeleteParams},
         runtime::{
             controller::{Action, Context},
+            events::EventType,
             reflector::ObjectRef,
         },
         ResourceExt,
@@ -17,22 +25,36 @@ use stackable_operator::{
     logging::controller::ReconcilerError,
 };
 use stackable_superset_crd::{
-    supersetdb::{SupersetDB, SupersetDBStatus, SupersetDBStatusCondition},
-    PYTHONPATH, SUPERSET_CONFIG_FILENAME,
+    supersetdb::{
+        DeletionPolicy, InitPhase, InitPhaseCondition, SupersetDB, SupersetDBStatus,
+        SupersetDBStatusCondition, DROP_SCHEMA_FINALIZER,
+    },
+    supersetdbbackup::{SupersetDBBackup, SupersetDBBackupStatusCondition},
+    JobDetails, SUPERSET_CONFIG_FILENAME,
 };
 use std::{sync::Arc, time::Duration};
-use strum::{EnumDiscriminants, IntoStaticStr};
+use strum::{EnumDiscriminants, IntoEnumIterator, IntoStaticStr};
 
 const FIELD_MANAGER_SCOPE: &str = "supersetcluster";
+/// Termination message of the schema migration if the schema existed before and was adopted
+const EXISTING_SCHEMA_MESSAGE: &str = "existing schema";
+/// Termination message of the schema migration if the schema to adopt did not exist
+const NEW_SCHEMA_MESSAGE: &str = "new schema";
 
 pub struct Ctx {
     pub client: stackable_operator::client::Client,
+    pub metrics: Arc<Metrics>,
 }
 
 #[derive(Snafu, Debug, EnumDiscriminants)]
 #[strum_discriminants(derive(IntoStaticStr))]
 #[allow(clippy::enum_variant_names)]
 pub enum Error {
+    #[snafu(display("failed to apply the ConfigMap of the Jobs for {}", superset_db))]
+    ApplyJobConfigMap {
+        source: stackable_operator::error::Error,
+        superset_db: ObjectRef<SupersetDB>,
+    },
     #[snafu(display("failed to apply Job for {}", superset_db))]
     ApplyJob {
         source: stackable_operator::error::Error,
@@ -48,13 +70,45 @@ pub enum Error {
     },
     #[snafu(display("database state is 'initializing' but failed to find job {}", init_job))]
     GetInitializationJob {
+        source: stackable_operator::kube::Error,
+        init_job: ObjectRef<Job>,
+    },
+    #[snafu(display("failed to collect the details of the finished Job"))]
+    JobDetails { source: crate::util::Error },
+    #[snafu(display("invalid credentials"))]
+    InvalidCredentials {
+        source: stackable_superset_crd::Error,
+    },
+    #[snafu(display("invalid config snippets"))]
+    InvalidConfigSnippets {
+        source: stackable_superset_crd::Error,
+    },
+    #[snafu(display("failed to check the credentials"))]
+    CredentialsCheck { source: crate::util::Error },
+    #[snafu(display("failed to apply pre-migration backup for {}", superset_db))]
+    ApplyPreMigrationBackup {
         source: stackable_operator::error::Error,
+        superset_db: ObjectRef<SupersetDB>,
+    },
+    #[snafu(display("failed to check for the previous initialization job {}", init_job))]
+    CheckPreviousInitializationJob {
+        source: stackable_operator::error::Error,
+        init_job: ObjectRef<Job>,
+    },
+    #[snafu(display("failed to delete the previous initialization job {}", init_job))]
+    DeletePreviousInitializationJob {
+        source: stackable_operator::kube::Error,
         init_job: ObjectRef<Job>,
     },
-    #[snafu(display("Failed to check whether the secret ({}) exists", secret))]
-    SecretCheck {
+    #[snafu(display("failed to apply Job to drop the schema of {}", superset_db))]
+    ApplyDropJob {
+        source: stackable_operator::error::Error,
+        superset_db: ObjectRef<SupersetDB>,
+    },
+    #[snafu(display("failed to update the finalizers of {}", superset_db))]
+    UpdateFinalizers {
         source: stackable_operator::error::Error,
-        secret: ObjectRef<Secret>,
+        superset_db: ObjectRef<SupersetDB>,
     },
 }
 
@@ -71,155 +125,822 @@ pub async fn reconcile_superset_db(
     ctx: Context<Ctx>,
 ) -> Result<Action> {
     tracing::info!("Starting reconcile");
+    let _reconcile_guard = ctx
+        .get_ref()
+        .metrics
+        .reconcile_started(SUPERSET_DB_CONTROLLER, &*superset_db);
 
     let client = &ctx.get_ref().client;
 
+    let deletion_policy = superset_db.spec.deletion_policy.unwrap_or_default();
+    if superset_db.metadata.deletion_timestamp.is_some() {
+        if has_drop_schema_finalizer(&superset_db) {
+            drop_schema(client, &superset_db, deletion_policy).await?;
+        }
+        return Ok(Action::await_change());
+    }
+    let finalizer_required = deletion_policy == DeletionPolicy::Delete;
+    if finalizer_required != has_drop_schema_finalizer(&superset_db) {
+        set_drop_schema_finalizer(client, &superset_db, finalizer_required).await?;
+    }
+
     if let Some(ref s) = superset_db.status {
-        match s.condition {
-            SupersetDBStatusCondition::Pending => {
-                let secret_exists = client
-                    .exists::<Secret>(
-                        &superset_db.spec.credentials_secret,
-                        superset_db.namespace().as_deref(),
+        match (s.condition, s.running_phase()) {
+            (SupersetDBStatusCondition::Pending, _) => {
+                // The job must not be started before all credentials are available
+                let credentials = superset_db.credentials().context(InvalidCredentialsSnafu)?;
+                if let Some(missing) = check_credentials(
+                    client,
+                    &credentials,
+                    &superset_db
+                        .namespace()
+                        .unwrap_or_else(|| "default".to_string()),
+                )
+                .await
+                .context(CredentialsCheckSnafu)?
+                {
+                    // The SupersetDB is reconciled again by the Secret watch when the
+                    // credentials are added
+                    publish_event(
+                        client,
+                        &*superset_db,
+                        EventType::Warning,
+                        "CredentialsMissing",
+                        format!("Waiting for the credentials: {missing}"),
                     )
-                    .await
-                    .with_context(|_| {
-                        let mut secret_ref =
-                            ObjectRef::<Secret>::new(&superset_db.spec.credentials_secret);
-                        if let Some(ns) = superset_db.namespace() {
-                            secret_ref = secret_ref.within(&ns);
+                    .await;
+                    return Ok(Action::await_change());
+                }
+
+                if superset_db.needs_migration() {
+                    if let Some(dump) = &superset_db.spec.backup_before_migration {
+                        let backup = SupersetDBBackup::pre_migration(&superset_db, dump);
+                        let backup = client
+                            .apply_patch(FIELD_MANAGER_SCOPE, &backup, &backup)
+                            .await
+                            .context(ApplyPreMigrationBackupSnafu {
+                                superset_db: ObjectRef::from_obj(&*superset_db),
+                            })?;
+                        match backup.status.map(|status| status.condition) {
+                            Some(SupersetDBBackupStatusCondition::Succeeded) => (),
+                            Some(SupersetDBBackupStatusCondition::Failed) => {
+                                apply_status(client, &superset_db, &s.failed()).await?;
+                                return Ok(Action::await_change());
+                            }
+                            // The backup is still running, the SupersetDB is requeued by the
+                            // backup watch as soon as it is finished
+                            _ => return Ok(Action::await_change()),
                         }
-                        SecretCheckSnafu { secret: secret_ref }
-                    })?;
-                if secret_exists {
-                    let job = build_init_job(&superset_db)?;
-                    client
-                        .apply_patch(FIELD_MANAGER_SCOPE, &job, &job)
-                        .await
-                        .context(ApplyJobSnafu {
-                            superset_db: ObjectRef::from_obj(&*superset_db),
-                        })?;
-                    // The job is started, update status to reflect new state
-                    client
-                        .apply_patch_status(FIELD_MANAGER_SCOPE, &*superset_db, &s.initializing())
-                        .await
-                        .context(ApplyStatusSnafu)?;
+                    }
+
+                    // The jobs of the previous initialization have to be removed because the
+                    // pod template of a job is immutable
+                    if delete_phase_jobs(client, &superset_db).await? {
+                        return Ok(Action::requeue(Duration::from_secs(5)));
+                    }
                 }
+
+                start_phase(
+                    client,
+                    &superset_db,
+                    &s.initializing(),
+                    InitPhase::SchemaMigration,
+                )
+                .await?;
+            }
+            (SupersetDBStatusCondition::Initializing, None) => {
+                // The initialization was started by an operator version without phases. Its Job
+                // has to finish before the phases run against the same schema.
+                if !remove_legacy_job(client, &superset_db).await? {
+                    return Ok(Action::await_change());
+                }
+                start_phase(
+                    client,
+                    &superset_db,
+                    &s.initializing(),
+                    InitPhase::SchemaMigration,
+                )
+                .await?;
             }
-            SupersetDBStatusCondition::Initializing => {
-                // In here, check the associated job that is running.
-                // If it is still running, do nothing. If it completed, set status to ready, if it failed, set status to failed.
+            (
+                SupersetDBStatusCondition::Initializing | SupersetDBStatusCondition::Ready,
+                Some(phase),
+            ) => {
+                // The examples are loaded while the database is already Ready
                 let ns = superset_db
                     .namespace()
                     .unwrap_or_else(|| "default".to_string());
-                let job_name = superset_db.job_name();
-                let job = client.get::<Job>(&job_name, Some(&ns)).await.context(
-                    GetInitializationJobSnafu {
-                        init_job: ObjectRef::<Job>::new(&job_name).within(&ns),
-                    },
-                )?;
-
-                let new_status = match get_job_state(&job) {
-                    JobState::Complete => Some(s.ready()),
-                    JobState::Failed => Some(s.failed()),
-                    JobState::InProgress => None,
+                let job_name = superset_db.phase_job_name(phase);
+                let job = match Api::<Job>::namespaced(client.as_kube_client(), &ns)
+                    .get(&job_name)
+                    .await
+                {
+                    Ok(job) => job,
+                    Err(stackable_operator::kube::Error::Api(response)) if response.code == 404 => {
+                        // The Job was deleted before its outcome was recorded, e.g. because it
+                        // was garbage-collected while the operator was not running. The phases
+                        // are idempotent, so the Job is simply run again.
+                        publish_event(
+                            client,
+                            &*superset_db,
+                            EventType::Warning,
+                            "JobNotFound",
+                            format!(
+                                "The Job {job_name} of the phase {phase:?} was deleted before it \
+                                 was observed as finished, it is created again"
+                            ),
+                        )
+                        .await;
+                        create_phase_job(client, &superset_db, phase).await?;
+                        return Ok(Action::await_change());
+                    }
+                    Err(source) => {
+                        return Err(Error::GetInitializationJob {
+                            source,
+                            init_job: ObjectRef::<Job>::new(&job_name).within(&ns),
+                        })
+                    }
+                };
+
+                let job_state = get_job_state(&job);
+                ctx.get_ref().metrics.job_finished(
+                    SUPERSET_DB_CONTROLLER,
+                    phase.job_suffix(),
+                    &job_state,
+                );
+                let phase_condition = match job_state {
+                    JobState::Complete => InitPhaseCondition::Succeeded,
+                    JobState::Failed => InitPhaseCondition::Failed,
+                    JobState::InProgress => return Ok(Action::await_change()),
+                };
+                let job_details = job_details(client, &job).await.context(JobDetailsSnafu)?;
+                publish_phase_event(client, &superset_db, phase, phase_condition, &job_details)
+                    .await;
+                let status = if phase == InitPhase::SchemaMigration
+                    && phase_condition == InitPhaseCondition::Succeeded
+                {
+                    let adopted = existing_schema_adopted(&superset_db, &job_details);
+                    if adopted.is_none() {
+                        publish_event(
+                            client,
+                            &*superset_db,
+                            EventType::Warning,
+                            "SchemaAdoptionUnknown",
+                            format!(
+                                "It could not be determined from the Job {} whether the schema \
+                                 existed before, the admin user and the examples are not added",
+                                job_details.name
+                            ),
+                        )
+                        .await;
+                    }
+                    s.existing_schema_adopted(adopted)
+                } else {
+                    s.clone()
+                };
+                let status = status.finish_phase(phase, phase_condition, job_details);
+
+                if phase_condition == InitPhaseCondition::Failed {
+                    // Failed examples do not affect the usability of the schema
+                    let status = if phase == InitPhase::Examples {
+                        status
+                    } else {
+                        status.failed()
+                    };
+                    apply_status(client, &superset_db, &status).await?;
+                } else {
+                    let (status, next_phase) = next_phase(&superset_db, status, phase);
+                    let status = match next_phase {
+                        Some(InitPhase::Examples) | None => {
+                            status.ready(&superset_db.spec.superset_version)
+                        }
+                        Some(_) => status,
+                    };
+                    match next_phase {
+                        Some(next_phase) => {
+                            start_phase(client, &superset_db, &status, next_phase).await?
+                        }
+                        None => apply_status(client, &superset_db, &status).await?,
+                    }
+                }
+            }
+            (SupersetDBStatusCondition::Ready, None) => {
+                let new_status = if s.superset_version.is_none() {
+                    // The schema was set up before the version was tracked
+                    Some(s.ready(&superset_db.spec.superset_version))
+                } else if superset_db.needs_migration() {
+                    Some(s.migrating())
+                } else {
+                    None
                 };
 
                 if let Some(ns) = new_status {
-                    client
-                        .apply_patch_status(FIELD_MANAGER_SCOPE, &*superset_db, &ns)
-                        .await
-                        .context(ApplyStatusSnafu)?;
+                    apply_status(client, &superset_db, &ns).await?;
                 }
             }
-            SupersetDBStatusCondition::Ready => (),
-            SupersetDBStatusCondition::Failed => (),
+            (SupersetDBStatusCondition::Failed, _) => (),
         }
     } else {
         // Status is none => initialize the status object as "Provisioned"
         let new_status = SupersetDBStatus::new();
-        client
-            .apply_patch_status(FIELD_MANAGER_SCOPE, &*superset_db, &new_status)
-            .await
-            .context(ApplyStatusSnafu)?;
+        apply_status(client, &superset_db, &new_status).await?;
     }
 
     Ok(Action::await_change())
 }
 
-fn build_init_job(superset_db: &SupersetDB) -> Result<Job> {
-    let config = "import os; SQLALCHEMY_DATABASE_URI = os.environ.get('DATABASE_URI')";
+/// Returns the phase which has to run after the given one, the phases which are not required are
+/// recorded as skipped
+fn next_phase(
+    superset_db: &SupersetDB,
+    mut status: SupersetDBStatus,
+    phase: InitPhase,
+) -> (SupersetDBStatus, Option<InitPhase>) {
+    // The admin user and the examples are not added to an adopted schema, nor to a schema of
+    // which it is unknown whether it was adopted, nor to a schema which was already set up by an
+    // earlier initialization and is only migrated
+    let new_schema =
+        status.existing_schema_adopted == Some(false) && status.superset_version.is_none();
+    for next_phase in InitPhase::iter().skip_while(|p| *p != phase).skip(1) {
+        let required = match next_phase {
+            InitPhase::SchemaMigration | InitPhase::RolesAndPermissions => true,
+            InitPhase::AdminCreation => new_schema,
+            InitPhase::Examples => superset_db.spec.load_examples && new_schema,
+        };
+        if required {
+            return (status, Some(next_phase));
+        }
+        status = status.skip_phase(next_phase);
+    }
+    (status, None)
+}
+
+/// Returns whether the finished schema migration has adopted an existing schema, or `None` if its
+/// Job does not tell, e.g. because its pod is already gone
+fn existing_schema_adopted(superset_db: &SupersetDB, job: &JobDetails) -> Option<bool> {
+    if !superset_db.spec.adopt_existing_schema.unwrap_or(false) {
+        return Some(false);
+    }
+    match job.termination_message.as_deref() {
+        Some(EXISTING_SCHEMA_MESSAGE) => Some(true),
+        Some(NEW_SCHEMA_MESSAGE) => Some(false),
+        _ => None,
+    }
+}
+
+/// Deletes the finished Job of an initialization which was started by an operator version without
+/// phases, returns whether the phases can start
+async fn remove_legacy_job(
+    client: &stackable_operator::client::Client,
+    superset_db: &SupersetDB,
+) -> Result<bool> {
+    let ns = superset_db
+        .namespace()
+        .unwrap_or_else(|| "default".to_string());
+    let job_name = superset_db.name();
+    let api = Api::<Job>::namespaced(client.as_kube_client(), &ns);
+    let job = match api.get(&job_name).await {
+        Ok(job) => job,
+        Err(stackable_operator::kube::Error::Api(response)) if response.code == 404 => {
+            return Ok(true)
+        }
+        Err(source) => {
+            return Err(Error::GetInitializationJob {
+                source,
+                init_job: ObjectRef::<Job>::new(&job_name).within(&ns),
+            })
+        }
+    };
+    // The SupersetDB is requeued by the Job watch when the Job has finished
+    if matches!(get_job_state(&job), JobState::InProgress) {
+        return Ok(false);
+    }
+    api.delete(&job_name, &DeleteParams::background())
+        .await
+        .context(DeletePreviousInitializationJobSnafu {
+            init_job: ObjectRef::<Job>::new(&job_name).within(&ns),
+        })?;
+    Ok(true)
+}
+
+/// Starts the Job of the given phase and records the phase as running
+async fn start_phase(
+    client: &stackable_operator::client::Client,
+    superset_db: &SupersetDB,
+    status: &SupersetDBStatus,
+    phase: InitPhase,
+) -> Result<()> {
+    create_phase_job(client, superset_db, phase).await?;
+    apply_status(client, superset_db, &status.start_phase(phase)).await
+}
 
-    let mut commands = vec![
-        format!("mkdir -p {PYTHONPATH}"),
-        format!("echo \"{config}\" > {PYTHONPATH}/{SUPERSET_CONFIG_FILENAME}"),
-        String::from(
-            "superset fab create-admin \
+async fn create_phase_job(
+    client: &stackable_operator::client::Client,
+    superset_db: &SupersetDB,
+    phase: InitPhase,
+) -> Result<()> {
+    apply_job_config_map(client, superset_db).await?;
+    let job = build_phase_job(superset_db, phase)?;
+    client
+        .apply_patch(FIELD_MANAGER_SCOPE, &job, &job)
+        .await
+        .context(ApplyJobSnafu {
+            superset_db: ObjectRef::from_obj(superset_db),
+        })?;
+    publish_event(
+        client,
+        superset_db,
+        EventType::Normal,
+        "CreatedJob",
+        format!(
+            "Created the Job {} for the phase {phase:?}",
+            superset_db.phase_job_name(phase)
+        ),
+    )
+    .await;
+    Ok(())
+}
+
+/// Deletes the Jobs of a previous initialization, returns whether any Job existed
+async fn delete_phase_jobs(
+    client: &stackable_operator::client::Client,
+    superset_db: &SupersetDB,
+) -> Result<bool> {
+    let ns = superset_db
+        .namespace()
+        .unwrap_or_else(|| "default".to_string());
+    let mut deleted = false;
+    for phase in InitPhase::iter() {
+        let job_name = superset_db.phase_job_name(phase);
+        let init_job = ObjectRef::<Job>::new(&job_name).within(&ns);
+        if client.exists::<Job>(&job_name, Some(&ns)).await.context(
+            CheckPreviousInitializationJobSnafu {
+                init_job: init_job.clone(),
+            },
+        )? {
+            Api::<Job>::namespaced(client.as_kube_client(), &ns)
+                .delete(&job_name, &DeleteParams::background())
+                .await
+                .context(DeletePreviousInitializationJobSnafu { init_job })?;
+            deleted = true;
+        }
+    }
+    Ok(deleted)
+}
+
+async fn publish_phase_event(
+    client: &stackable_operator::client::Client,
+    superset_db: &SupersetDB,
+    phase: InitPhase,
+    condition: InitPhaseCondition,
+    job: &JobDetails,
+) {
+    let note = format!("The phase {phase:?} has {condition:?}");
+    let (type_, note) = if condition == InitPhaseCondition::Failed {
+        (EventType::Warning, job_failure_note(note, job))
+    } else {
+        (EventType::Normal, note)
+    };
+    publish_event(
+        client,
+        superset_db,
+        type_,
+        &format!("{phase:?}{condition:?}"),
+        note,
+    )
+    .await;
+}
+
+/// Patches the status and publishes an event if the condition has changed
+async fn apply_status(
+    client: &stackable_operator::client::Client,
+    superset_db: &SupersetDB,
+    status: &SupersetDBStatus,
+) -> Result<()> {
+    client
+        .apply_patch_status(FIELD_MANAGER_SCOPE, superset_db, status)
+        .await
+        .context(ApplyStatusSnafu)?;
+
+    let previous_condition = superset_db.status.as_ref().map(|status| status.condition);
+    if previous_condition != Some(status.condition) {
+        let type_ = if status.condition == SupersetDBStatusCondition::Failed {
+            EventType::Warning
+        } else {
+            EventType::Normal
+        };
+        let note = match previous_condition {
+            Some(previous_condition) => format!(
+                "The condition changed from {previous_condition:?} to {:?}",
+                status.condition
+            ),
+            None => format!("The condition is {:?}", status.condition),
+        };
+        let failed_job = status
+            .phases
+            .iter()
+            .filter(|_| status.condition == SupersetDBStatusCondition::Failed)
+            .find(|phase_status| phase_status.condition == InitPhaseCondition::Failed)
+            .and_then(|phase_status| phase_status.job.as_ref());
+        let note = match failed_job {
+            Some(job) => job_failure_note(note, job),
+            None => note,
+        };
+        publish_event(
+            client,
+            superset_db,
+            type_,
+            &format!("{:?}", status.condition),
+            note,
+        )
+        .await;
+    }
+    Ok(())
+}
+
+fn has_drop_schema_finalizer(superset_db: &SupersetDB) -> bool {
+    superset_db
+        .metadata
+        .finalizers
+        .iter()
+        .flatten()
+        .any(|finalizer| finalizer == DROP_SCHEMA_FINALIZER)
+}
+
+async fn set_drop_schema_finalizer(
+    client: &stackable_operator::client::Client,
+    superset_db: &SupersetDB,
+    enabled: bool,
+) -> Result<()> {
+    let mut finalizers = superset_db.metadata.finalizers.clone().unwrap_or_default();
+    finalizers.retain(|finalizer| finalizer != DROP_SCHEMA_FINALIZER);
+    if enabled {
+        finalizers.push(DROP_SCHEMA_FINALIZER.to_string());
+    }
+    // The resource version makes the patch fail instead of overwriting concurrent changes to the
+    // finalizers
+    client
+        .merge_patch(
+            superset_db,
+            json!({
+                "metadata": {
+                    "finalizers": finalizers,
+                    "resourceVersion": superset_db.resource_version(),
+                }
+            }),
+        )
+        .await
+        .context(UpdateFinalizersSnafu {
+            superset_db: ObjectRef::from_obj(superset_db),
+        })?;
+    Ok(())
+}
+
+/// Drops the Superset tables of a deleted SupersetDB and releases it afterwards.
+///
+/// If the drop job fails, the SupersetDB is kept until the deletion policy is changed to `Retain`.
+async fn drop_schema(
+    client: &stackable_operator::client::Client,
+    superset_db: &SupersetDB,
+    deletion_policy: DeletionPolicy,
+) -> Result<()> {
+    if deletion_policy == DeletionPolicy::Retain {
+        return set_drop_schema_finalizer(client, superset_db, false).await;
+    }
+
+    apply_job_config_map(client, superset_db).await?;
+    let job = build_drop_job(superset_db)?;
+    let job = client
+        .apply_patch(FIELD_MANAGER_SCOPE, &job, &job)
+        .await
+        .context(ApplyDropJobSnafu {
+            superset_db: ObjectRef::from_obj(superset_db),
+        })?;
+    match get_job_state(&job) {
+        JobState::Complete => set_drop_schema_finalizer(client, superset_db, false).await?,
+        JobState::Failed => tracing::warn!(
+            "Failed to drop the Superset tables, set the deletion policy to Retain to delete the SupersetDB anyway"
+        ),
+        JobState::InProgress => (),
+    }
+    Ok(())
+}
+
+/// Applies the ConfigMap with the `superset_config.py` which is mounted into all Jobs of the
+/// SupersetDB
+async fn apply_job_config_map(
+    client: &stackable_operator::client::Client,
+    superset_db: &SupersetDB,
+) -> Result<()> {
+    let config_map = build_job_config_map(superset_db)?;
+    client
+        .apply_patch(FIELD_MANAGER_SCOPE, &config_map, &config_map)
+        .await
+        .context(ApplyJobConfigMapSnafu {
+            superset_db: ObjectRef::from_obj(superset_db),
+        })?;
+    Ok(())
+}
+
+/// Builds the ConfigMap with the `superset_config.py` which is mounted into all Jobs of the
+/// SupersetDB
+pub fn build_job_config_map(superset_db: &SupersetDB) -> Result<ConfigMap> {
+    let credentials = superset_db.credentials().context(InvalidCredentialsSnafu)?;
+    let config = superset_db
+        .config_snippets()
+        .and_then(|snippets| superset_job_config(&credentials, snippets))
+        .context(InvalidConfigSnippetsSnafu)?;
+    Ok(ConfigMap {
+        metadata: ObjectMetaBuilder::new()
+            .name(superset_db.job_config_map_name())
+            .namespace_opt(superset_db.namespace())
+            .ownerreference_from_resource(superset_db, None, Some(true))
+            .context(ObjectMissingMetadataForOwnerRefSnafu)?
+            .build(),
+        data: Some([(SUPERSET_CONFIG_FILENAME.to_string(), config)].into()),
+        ..ConfigMap::default()
+    })
+}
+
+/// Builds the job which runs the given initialization phase.
+pub fn build_phase_job(superset_db: &SupersetDB, phase: InitPhase) -> Result<Job> {
+    let credentials = superset_db.credentials().context(InvalidCredentialsSnafu)?;
+
+    let mut commands = Vec::new();
+    match phase {
+        InitPhase::SchemaMigration => {
+            if superset_db.spec.adopt_existing_schema.unwrap_or(false) {
+                // The marker tells the operator whether to leave the content of the schema untouched
+                commands.push(format!(
+                    "if python -c '\
+                        import sys; \
+                        from sqlalchemy import create_engine; \
+                        from superset_config import SQLALCHEMY_DATABASE_URI; \
+                        engine = create_engine(SQLALCHEMY_DATABASE_URI); \
+                        sys.exit(0 if engine.dialect.has_table(engine.connect(), \"ab_user\") else 1)'; \
+                    then echo \"{EXISTING_SCHEMA_MESSAGE}\" > /dev/termination-log; \
+                    else echo \"{NEW_SCHEMA_MESSAGE}\" > /dev/termination-log; fi"
+                ));
+            }
+            commands.push(with_failure_summary("db upgrade", "superset db upgrade"));
+        }
+        InitPhase::RolesAndPermissions => {
+            commands.push(with_failure_summary("init", "superset init"));
+        }
+        InitPhase::AdminCreation => {
+            commands.push(with_failure_summary(
+                "create-admin",
+                "superset fab create-admin \
                     --username \"$ADMIN_USERNAME\" \
                     --firstname \"$ADMIN_FIRSTNAME\" \
                     --lastname \"$ADMIN_LASTNAME\" \
                     --email \"$ADMIN_EMAIL\" \
                     --password \"$ADMIN_PASSWORD\"",
-        ),
-        String::from("superset db upgrade"),
-        String::from("superset init"),
-    ];
-    if superset_db.spec.load_examples {
-        commands.push(String::from("superset load_examples"));
+            ));
+        }
+        InitPhase::Examples => {
+            commands.push(with_failure_summary(
+                "load_examples",
+                "superset load_examples",
+            ));
+        }
     }
 
-    let secret = &superset_db.spec.credentials_secret;
-
-    let container = ContainerBuilder::new("superset-init-db")
-        .image(format!(
-            "docker.stackable.tech/stackable/superset:{}-stackable1",
-            superset_db.spec.superset_version
-        ))
+    let mut cb = ContainerBuilder::new("superset-init-db");
+    cb.image(superset_db.superset_image())
         .command(vec!["/bin/bash".to_string()])
         .args(vec![
             String::from("-euo"),
             String::from("pipefail"),
             String::from("-c"),
             commands.join("; "),
-        ])
-        .add_env_var_from_secret("DATABASE_URI", secret, "connections.sqlalchemyDatabaseUri")
-        .add_env_var_from_secret("ADMIN_USERNAME", secret, "adminUser.username")
-        .add_env_var_from_secret("ADMIN_FIRSTNAME", secret, "adminUser.firstname")
-        .add_env_var_from_secret("ADMIN_LASTNAME", secret, "adminUser.lastname")
-        .add_env_var_from_secret("ADMIN_EMAIL", secret, "adminUser.email")
-        .add_env_var_from_secret("ADMIN_PASSWORD", secret, "adminUser.password")
-        .build();
+        ]);
+    if phase == InitPhase::AdminCreation {
+        cb.add_env_var_from_secret(
+            "ADMIN_USERNAME",
+            &credentials.admin_username.name,
+            &credentials.admin_username.key,
+        )
+        .add_env_var_from_secret(
+            "ADMIN_FIRSTNAME",
+            &credentials.admin_firstname.name,
+            &credentials.admin_firstname.key,
+        )
+        .add_env_var_from_secret(
+            "ADMIN_LASTNAME",
+            &credentials.admin_lastname.name,
+            &credentials.admin_lastname.key,
+        )
+        .add_env_var_from_secret(
+            "ADMIN_EMAIL",
+            &credentials.admin_email.name,
+            &credentials.admin_email.key,
+        )
+        .add_env_var_from_secret(
+            "ADMIN_PASSWORD",
+            &credentials.admin_password.name,
+            &credentials.admin_password.key,
+        );
+    }
+    add_superset_config_env(&mut cb, &credentials);
+    let container = cb.build();
+
+    build_job(
+        superset_db,
+        superset_db.phase_job_name(phase),
+        format!("{}-{}", superset_db.name(), phase.job_suffix()),
+        container,
+    )
+}
 
+/// Builds the job which drops all Superset tables, including the Alembic version table.
+fn build_drop_job(superset_db: &SupersetDB) -> Result<Job> {
+    let credentials = superset_db.credentials().context(InvalidCredentialsSnafu)?;
+
+    let command = String::from(
+        "python -c '\
+            from superset.app import create_app; \
+            from superset.extensions import db; \
+            app = create_app(); \
+            app.app_context().push(); \
+            db.drop_all(); \
+            db.engine.execute(\"DROP TABLE IF EXISTS alembic_version\")'",
+    );
+
+    let mut cb = ContainerBuilder::new("superset-drop-db");
+    cb.image(superset_db.superset_image())
+        .command(vec!["/bin/bash".to_string()])
+        .args(vec![
+            String::from("-euo"),
+            String::from("pipefail"),
+            String::from("-c"),
+            command,
+        ]);
+    add_superset_config_env(&mut cb, &credentials);
+    let container = cb.build();
+
+    build_job(
+        superset_db,
+        superset_db.drop_job_name(),
+        superset_db.drop_job_name(),
+        container,
+    )
+}
+
+fn build_job(
+    superset_db: &SupersetDB,
+    job_name: String,
+    pod_name: String,
+    mut container: Container,
+) -> Result<Job> {
+    let credentials = superset_db.credentials().context(InvalidCredentialsSnafu)?;
+    // Failures which are not summarized by the script are explained by the end of the log
+    container.termination_message_policy = Some("FallbackToLogsOnError".to_string());
+    let container_name = container.name.clone();
+    let mut pod_spec = PodSpec {
+        containers: vec![container],
+        restart_policy: Some("Never".to_string()),
+        ..Default::default()
+    };
+    add_job_config_volume(
+        &mut pod_spec,
+        &container_name,
+        &superset_db.job_config_map_name(),
+        &superset_db.spec.config_snippets,
+    );
+    add_metadata_database_tls(
+        &mut pod_spec,
+        &container_name,
+        &credentials.metadata_database,
+    );
+    if let Some(python_packages) = &superset_db.spec.python_packages {
+        add_python_packages(&mut pod_spec, &container_name, python_packages);
+    }
+    add_image_pull_settings(&mut pod_spec, &superset_db.product_image());
     let pod = PodTemplateSpec {
-        metadata: Some(
-            ObjectMetaBuilder::new()
-                .name(format!("{}-init", superset_db.name()))
-                .build(),
-        ),
-        spec: Some(PodSpec {
-            containers: vec![container],
-            restart_policy: Some("Never".to_string()),
-            ..Default::default()
-        }),
+        metadata: Some(ObjectMetaBuilder::new().name(pod_name).build()),
+        spec: Some(pod_spec),
     };
 
     let job = Job {
         metadata: ObjectMetaBuilder::new()
-            .name(superset_db.name())
+            .name(job_name)
             .namespace_opt(superset_db.namespace())
             .ownerreference_from_resource(superset_db, None, Some(true))
             .context(ObjectMissingMetadataForOwnerRefSnafu)?
             .build(),
-        spec: Some(JobSpec {
-            template: pod,
-            ..Default::default()
-        }),
+        spec: Some(job_spec(pod, superset_db.spec.jobs.as_ref())),
         status: None,
     };
 
     Ok(job)
 }
 
-pub fn error_policy(_error: &Error, _ctx: Context<Ctx>) -> Action {
+pub fn error_policy(error: &Error, ctx: Context<Ctx>) -> Action {
+    ctx.get_ref()
+        .metrics
+        .reconcile_failed(SUPERSET_DB_CONTROLLER, error.category());
     Action::requeue(Duration::from_secs(5))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stackable_superset_crd::supersetdb::SupersetDBSpec;
+
+    fn superset_db(load_examples: bool) -> SupersetDB {
+        let spec: SupersetDBSpec = serde_yaml::from_str(&format!(
+            "supersetVersion: 1.4.1\nloadExamples: {load_examples}\n"
+        ))
+        .unwrap();
+        SupersetDB::new("superset", spec)
+    }
+
+    fn status(existing_schema_adopted: Option<bool>) -> SupersetDBStatus {
+        SupersetDBStatus::new()
+            .initializing()
+            .existing_schema_adopted(existing_schema_adopted)
+    }
+
+    fn skipped_phases(status: &SupersetDBStatus) -> Vec<InitPhase> {
+        status
+            .phases
+            .iter()
+            .filter(|phase| phase.condition == InitPhaseCondition::Skipped)
+            .map(|phase| phase.phase)
+            .collect()
+    }
+
+    #[test]
+    fn next_phase_runs_all_phases_on_a_new_schema() {
+        let superset_db = superset_db(true);
+        let (status, next) = next_phase(
+            &superset_db,
+            status(Some(false)),
+            InitPhase::SchemaMigration,
+        );
+        assert_eq!(next, Some(InitPhase::RolesAndPermissions));
+        let (status, next) = next_phase(&superset_db, status, InitPhase::RolesAndPermissions);
+        assert_eq!(next, Some(InitPhase::AdminCreation));
+        let (status, next) = next_phase(&superset_db, status, InitPhase::AdminCreation);
+        assert_eq!(next, Some(InitPhase::Examples));
+        let (status, next) = next_phase(&superset_db, status, InitPhase::Examples);
+        assert_eq!(next, None);
+        assert!(skipped_phases(&status).is_empty());
+    }
+
+    #[test]
+    fn next_phase_skips_the_examples_if_they_are_not_loaded() {
+        let (status, next) = next_phase(
+            &superset_db(false),
+            status(Some(false)),
+            InitPhase::AdminCreation,
+        );
+        assert_eq!(next, None);
+        assert_eq!(skipped_phases(&status), vec![InitPhase::Examples]);
+    }
+
+    #[test]
+    fn next_phase_skips_the_admin_and_the_examples_on_an_adopted_schema() {
+        for existing_schema_adopted in [Some(true), None] {
+            let (status, next) = next_phase(
+                &superset_db(true),
+                status(existing_schema_adopted),
+                InitPhase::RolesAndPermissions,
+            );
+            assert_eq!(next, None);
+            assert_eq!(
+                skipped_phases(&status),
+                vec![InitPhase::AdminCreation, InitPhase::Examples]
+            );
+        }
+    }
+
+    #[test]
+    fn next_phase_skips_the_admin_and_the_examples_on_a_migrated_schema() {
+        let migrating = SupersetDBStatus::new()
+            .ready("1.3.2")
+            .migrating()
+            .initializing()
+            .existing_schema_adopted(Some(false));
+        let (status, next) = next_phase(&superset_db(true), migrating, InitPhase::SchemaMigration);
+        assert_eq!(next, Some(InitPhase::RolesAndPermissions));
+        let (status, next) = next_phase(&superset_db(true), status, InitPhase::RolesAndPermissions);
+        assert_eq!(next, None);
+        assert_eq!(
+            skipped_phases(&status),
+            vec![InitPhase::AdminCreation, InitPhase::Examples]
+        );
+    }
+
+    #[test]
+    fn next_phase_always_runs_the_roles_and_permissions() {
+        let (status, next) = next_phase(
+            &superset_db(false),
+            status(None),
+            InitPhase::SchemaMigration,
+        );
+        assert_eq!(next, Some(InitPhase::RolesAndPermissions));
+        assert!(skipped_phases(&status).is_empty());
+    }
+}
@@ -0,0 +1,378 @@
+//! Restores the metadata database of a [`SupersetDB`] from a [`SupersetDBBackup`] for each
+//! [`SupersetDBRestore`]
+
+use crate::{
+    metrics::{Metrics, SUPERSET_DB_RESTORE_CONTROLLER},
+    superset_db_backup_controller::backup_volume,
+    util::{
+        add_image_pull_settings, add_metadata_database_env, add_metadata_database_tls,
+        database_client_preamble, get_job_state, job_spec, metadata_database_uri_commands,
+        publish_event, JobState,
+    },
+};
+
+use serde_json::json;
+use snafu::{ResultExt, Snafu};
+use stackable_operator::{
+    builder::{ContainerBuilder, ObjectMetaBuilder},
+    k8s_openapi::api::{
+        batch::v1::Job,
+        core::v1::{PodSpec, PodTemplateSpec},
+    },
+    kube::{
+        api::Api,
+        runtime::{
+            controller::{Action, Context},
+            events::EventType,
+            reflector::ObjectRef,
+        },
+        ResourceExt,
+    },
+    logging::controller::ReconcilerError,
+};
+use stackable_superset_crd::{
+    supersetdb::SupersetDB,
+    supersetdbbackup::{SupersetDBBackup, SupersetDBBackupStatusCondition, BACKUP_DIR},
+    supersetdbrestore::{
+        SupersetDBRestore, SupersetDBRestoreStatus, SupersetDBRestoreStatusCondition,
+    },
+    DatabaseType, SupersetCluster,
+};
+use std::{sync::Arc, time::Duration};
+use strum::{EnumDiscriminants, IntoStaticStr};
+
+const FIELD_MANAGER_SCOPE: &str = "supersetcluster";
+
+pub struct Ctx {
+    pub client: stackable_operator::client::Client,
+    pub metrics: Arc<Metrics>,
+}
+
+#[derive(Snafu, Debug, EnumDiscriminants)]
+#[strum_discriminants(derive(IntoStaticStr))]
+#[allow(clippy::enum_variant_names)]
+pub enum Error {
+    #[snafu(display("failed to apply Job for {}", restore))]
+    ApplyJob {
+        source: stackable_operator::error::Error,
+        restore: ObjectRef<SupersetDBRestore>,
+    },
+    #[snafu(display("failed to update status"))]
+    ApplyStatus {
+        source: stackable_operator::error::Error,
+    },
+    #[snafu(display("failed to update status of {}", superset_db))]
+    ApplySupersetDBStatus {
+        source: stackable_operator::error::Error,
+        superset_db: ObjectRef<SupersetDB>,
+    },
+    #[snafu(display("object is missing metadata to build owner reference"))]
+    ObjectMissingMetadataForOwnerRef {
+        source: stackable_operator::error::Error,
+    },
+    #[snafu(display("invalid credentials"))]
+    InvalidCredentials {
+        source: stackable_superset_crd::Error,
+    },
+    #[snafu(display("failed to retrieve backup {}", backup))]
+    BackupRetrieval {
+        source: stackable_operator::error::Error,
+        backup: ObjectRef<SupersetDBBackup>,
+    },
+    #[snafu(display("failed to retrieve superset db {}", superset_db))]
+    SupersetDBRetrieval {
+        source: stackable_operator::error::Error,
+        superset_db: ObjectRef<SupersetDB>,
+    },
+    #[snafu(display("failed to check whether the superset cluster {} is stopped", superset))]
+    SupersetClusterCheck {
+        source: stackable_operator::error::Error,
+        superset: ObjectRef<SupersetCluster>,
+    },
+    #[snafu(display("restore state is 'restoring' but failed to find job {}", restore_job))]
+    GetRestoreJob {
+        source: stackable_operator::kube::Error,
+        restore_job: ObjectRef<Job>,
+    },
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl ReconcilerError for Error {
+    fn category(&self) -> &'static str {
+        ErrorDiscriminants::from(self).into()
+    }
+}
+
+pub async fn reconcile_superset_db_restore(
+    restore: Arc<SupersetDBRestore>,
+    ctx: Context<Ctx>,
+) -> Result<Action> {
+    tracing::info!("Starting reconcile");
+    let _reconcile_guard = ctx
+        .get_ref()
+        .metrics
+        .reconcile_started(SUPERSET_DB_RESTORE_CONTROLLER, &*restore);
+
+    let client = &ctx.get_ref().client;
+    let ns = restore.namespace().unwrap_or_else(|| "default".to_string());
+
+    if let Some(ref s) = restore.status {
+        match s.condition {
+            SupersetDBRestoreStatusCondition::Pending => {
+                let backup = client
+                    .get::<SupersetDBBackup>(&restore.spec.backup, Some(&ns))
+                    .await
+                    .context(BackupRetrievalSnafu {
+                        backup: ObjectRef::<SupersetDBBackup>::new(&restore.spec.backup)
+                            .within(&ns),
+                    })?;
+                let backup_succeeded = backup.status.as_ref().map(|status| status.condition)
+                    == Some(SupersetDBBackupStatusCondition::Succeeded);
+                if !backup_succeeded {
+                    tracing::info!("Waiting for backup {} to succeed", backup.name());
+                    return Ok(Action::requeue(Duration::from_secs(10)));
+                }
+
+                // The SupersetDB is named after its cluster, which must not access the database
+                // while it is restored
+                let superset_name = &backup.spec.superset_db;
+                let superset_ref = ObjectRef::<SupersetCluster>::new(superset_name).within(&ns);
+                let superset_running = if client
+                    .exists::<SupersetCluster>(superset_name, Some(&ns))
+                    .await
+                    .context(SupersetClusterCheckSnafu {
+                        superset: superset_ref.clone(),
+                    })? {
+                    let superset = client
+                        .get::<SupersetCluster>(superset_name, Some(&ns))
+                        .await
+                        .context(SupersetClusterCheckSnafu {
+                            superset: superset_ref,
+                        })?;
+                    !superset.spec.stopped.unwrap_or(false)
+                } else {
+                    false
+                };
+                if superset_running {
+                    tracing::info!(
+                        "Waiting for superset cluster {} to be stopped before restoring",
+                        superset_name
+                    );
+                    return Ok(Action::requeue(Duration::from_secs(10)));
+                }
+
+                let superset_db = client
+                    .get::<SupersetDB>(&backup.spec.superset_db, Some(&ns))
+                    .await
+                    .context(SupersetDBRetrievalSnafu {
+                        superset_db: ObjectRef::<SupersetDB>::new(&backup.spec.superset_db)
+                            .within(&ns),
+                    })?;
+
+                let job = build_restore_job(&restore, &backup, &superset_db)?;
+                client
+                    .apply_patch(FIELD_MANAGER_SCOPE, &job, &job)
+                    .await
+                    .context(ApplyJobSnafu {
+                        restore: ObjectRef::from_obj(&*restore),
+                    })?;
+                client
+                    .apply_patch_status(FIELD_MANAGER_SCOPE, &*restore, &s.restoring())
+                    .await
+                    .context(ApplyStatusSnafu)?;
+            }
+            SupersetDBRestoreStatusCondition::Restoring => {
+                let job_name = restore.job_name();
+                let job = match Api::<Job>::namespaced(client.as_kube_client(), &ns)
+                    .get(&job_name)
+                    .await
+                {
+                    Ok(job) => job,
+                    Err(stackable_operator::kube::Error::Api(response)) if response.code == 404 => {
+                        // The Job was deleted before its outcome was recorded, e.g. because it
+                        // was garbage-collected while the operator was not running. The restore
+                        // is started over.
+                        publish_event(
+                            client,
+                            &*restore,
+                            EventType::Warning,
+                            "JobNotFound",
+                            format!(
+                                "The restore Job {job_name} was deleted before it was observed as \
+                                 finished, the restore is started again"
+                            ),
+                        )
+                        .await;
+                        client
+                            .apply_patch_status(
+                                FIELD_MANAGER_SCOPE,
+                                &*restore,
+                                &SupersetDBRestoreStatus::new(),
+                            )
+                            .await
+                            .context(ApplyStatusSnafu)?;
+                        return Ok(Action::await_change());
+                    }
+                    Err(source) => {
+                        return Err(Error::GetRestoreJob {
+                            source,
+                            restore_job: ObjectRef::<Job>::new(&job_name).within(&ns),
+                        })
+                    }
+                };
+
+                let job_state = get_job_state(&job);
+                ctx.get_ref().metrics.job_finished(
+                    SUPERSET_DB_RESTORE_CONTROLLER,
+                    "restore",
+                    &job_state,
+                );
+                let new_status = match job_state {
+                    JobState::Complete => {
+                        record_restored_schema_version(client, &ns, &restore).await?;
+                        Some(s.succeeded())
+                    }
+                    JobState::Failed => Some(s.failed()),
+                    JobState::InProgress => None,
+                };
+
+                if let Some(ns) = new_status {
+                    client
+                        .apply_patch_status(FIELD_MANAGER_SCOPE, &*restore, &ns)
+                        .await
+                        .context(ApplyStatusSnafu)?;
+                }
+            }
+            SupersetDBRestoreStatusCondition::Succeeded => (),
+            SupersetDBRestoreStatusCondition::Failed => (),
+        }
+    } else {
+        client
+            .apply_patch_status(
+                FIELD_MANAGER_SCOPE,
+                &*restore,
+                &SupersetDBRestoreStatus::new(),
+            )
+            .await
+            .context(ApplyStatusSnafu)?;
+    }
+
+    Ok(Action::await_change())
+}
+
+/// Records the Superset version of the restored schema in the status of the SupersetDB, so that
+/// the schema is migrated again if it differs from the desired version. The condition is left as
+/// it is.
+async fn record_restored_schema_version(
+    client: &stackable_operator::client::Client,
+    ns: &str,
+    restore: &SupersetDBRestore,
+) -> Result<()> {
+    let backup = client
+        .get::<SupersetDBBackup>(&restore.spec.backup, Some(ns))
+        .await
+        .context(BackupRetrievalSnafu {
+            backup: ObjectRef::<SupersetDBBackup>::new(&restore.spec.backup).within(ns),
+        })?;
+    let superset_db_ref = ObjectRef::<SupersetDB>::new(&backup.spec.superset_db).within(ns);
+    let superset_db = client
+        .get::<SupersetDB>(&backup.spec.superset_db, Some(ns))
+        .await
+        .context(SupersetDBRetrievalSnafu {
+            superset_db: superset_db_ref.clone(),
+        })?;
+
+    if let (Some(_), Some(version)) = (
+        &superset_db.status,
+        backup
+            .status
+            .as_ref()
+            .and_then(|status| status.superset_version.as_deref()),
+    ) {
+        client
+            .merge_patch_status(&superset_db, &json!({ "supersetVersion": version }))
+            .await
+            .context(ApplySupersetDBStatusSnafu {
+                superset_db: superset_db_ref,
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Builds the restore job. When run it will replace the contents of the metadata database with
+/// the dump of the backup.
+fn build_restore_job(
+    restore: &SupersetDBRestore,
+    backup: &SupersetDBBackup,
+    superset_db: &SupersetDB,
+) -> Result<Job> {
+    let db_type = backup.spec.dump.db_type;
+    let dump_file = backup.dump_file();
+    let credentials = superset_db.credentials().context(InvalidCredentialsSnafu)?;
+
+    let mut commands = metadata_database_uri_commands(&credentials.metadata_database);
+    commands.extend(database_client_preamble(
+        db_type,
+        &credentials.metadata_database,
+    ));
+    commands.push(match db_type {
+        DatabaseType::Postgresql => {
+            format!("pg_restore --dbname=\"$uri\" --clean --if-exists --no-owner {dump_file}")
+        }
+        DatabaseType::Mysql => {
+            format!("mysql \"${{mysql_args[@]}}\" \"$database\" < {dump_file}")
+        }
+    });
+
+    let mut cb = ContainerBuilder::new("superset-db-restore");
+    cb.image(backup.spec.dump.client_image(&superset_db.product_image()))
+        .command(vec!["/bin/bash".to_string()])
+        .args(vec![
+            String::from("-euo"),
+            String::from("pipefail"),
+            String::from("-c"),
+            commands.join("\n"),
+        ])
+        .add_volume_mount("backup", BACKUP_DIR);
+    add_metadata_database_env(&mut cb, &credentials.metadata_database, "DATABASE_URI");
+    let container = cb.build();
+
+    let mut pod_spec = PodSpec {
+        containers: vec![container],
+        restart_policy: Some("Never".to_string()),
+        volumes: Some(vec![backup_volume(&backup.spec.dump.claim_name)]),
+        ..Default::default()
+    };
+    add_metadata_database_tls(
+        &mut pod_spec,
+        "superset-db-restore",
+        &credentials.metadata_database,
+    );
+    add_image_pull_settings(&mut pod_spec, &superset_db.product_image());
+    let pod = PodTemplateSpec {
+        metadata: Some(ObjectMetaBuilder::new().name(restore.job_name()).build()),
+        spec: Some(pod_spec),
+    };
+
+    let job = Job {
+        metadata: ObjectMetaBuilder::new()
+            .name(restore.job_name())
+            .namespace_opt(restore.namespace())
+            .ownerreference_from_resource(restore, None, Some(true))
+            .context(ObjectMissingMetadataForOwnerRefSnafu)?
+            .build(),
+        spec: Some(job_spec(pod, restore.spec.job.as_ref())),
+        status: None,
+    };
+
+    Ok(job)
+}
+
+pub fn error_policy(error: &Error, ctx: Context<Ctx>) -> Action {
+    ctx.get_ref()
+        .metrics
+        .reconcile_failed(SUPERSET_DB_RESTORE_CONTROLLER, error.category());
+    Action::requeue(Duration::from_secs(5))
+}
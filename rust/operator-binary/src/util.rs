@@ -1,6 +1,51 @@
-use snafu::{OptionExt, Snafu};
-use stackable_operator::k8s_openapi::api::batch::v1::Job;
-use stackable_superset_crd::SupersetCluster;
+use std::collections::BTreeMap;
+
+use sha2::{Digest, Sha256};
+use snafu::{OptionExt, ResultExt, Snafu};
+use stackable_operator::{
+    builder::ContainerBuilder,
+    client::Client,
+    k8s_openapi::{
+        api::{
+            batch::v1::{Job, JobSpec},
+            core::v1::{
+                ConfigMap, ConfigMapProjection, ConfigMapVolumeSource, EmptyDirVolumeSource,
+                EnvVar, KeyToPath, PersistentVolumeClaimVolumeSource, Pod, PodSecurityContext,
+                PodSpec, PodTemplateSpec, ProjectedVolumeSource, Secret, SecretVolumeSource,
+                Volume, VolumeMount, VolumeProjection,
+            },
+        },
+        apimachinery::pkg::apis::meta::v1::Time,
+    },
+    kube::{
+        api::{Api, ListParams},
+        runtime::{
+            events::{Event, EventType, Recorder, Reporter},
+            reflector::ObjectRef,
+        },
+        Resource, ResourceExt,
+    },
+};
+use stackable_superset_crd::{
+    CaCertificate, ConfigSnippet, ConfigSnippetMode, DatabaseType, JobDetails, JobSettings,
+    MetadataDatabaseConnection, MetadataDatabaseTls, ProductImage, PythonPackages,
+    ResolvedCredentials, SecretKeyRef, SupersetCluster, Wheelhouse, CONFIG_SNIPPETS_DIR,
+    PYTHONPATH, PYTHON_PACKAGES_DIR, SUPERSET_CONFIG_FILENAME, WHEELHOUSE_DIR,
+};
+
+const METADATA_DB_USERNAME_ENV: &str = "METADATA_DB_USERNAME";
+const METADATA_DB_PASSWORD_ENV: &str = "METADATA_DB_PASSWORD";
+
+/// The volume with the generated files of a Job
+const JOB_CONFIG_VOLUME: &str = "job-config";
+/// The volume with the modules of the config snippets
+pub const CONFIG_SNIPPETS_VOLUME: &str = "config-snippets";
+
+/// The reporting controller of the published events
+const EVENT_REPORTER: &str = "superset-operator";
+
+/// The init container which installs the `pythonPackages`
+pub const PYTHON_PACKAGES_CONTAINER: &str = "install-python-packages";
 
 #[derive(Snafu, Debug)]
 #[allow(clippy::enum_variant_names)]
@@ -8,8 +53,150 @@ use stackable_superset_crd::SupersetCluster;
 pub enum Error {
     #[snafu(display("object defines no version"))]
     ObjectHasNoVersion,
-    #[snafu(display("object defines no stats exporter version"))]
-    ObjectHasNoStatsdExporterVersion,
+    #[snafu(display("failed to list the pods of Job {}", job))]
+    ListJobPods {
+        source: stackable_operator::error::Error,
+        job: ObjectRef<Job>,
+    },
+    #[snafu(display("failed to retrieve the credentials Secret {}", secret))]
+    GetCredentialsSecret {
+        source: stackable_operator::error::Error,
+        secret: ObjectRef<Secret>,
+    },
+    #[snafu(display("failed to look up the credentials Secret {}", secret))]
+    LookUpCredentialsSecret {
+        source: stackable_operator::kube::Error,
+        secret: ObjectRef<Secret>,
+    },
+    #[snafu(display("the credentials Secret {} does not contain the key {}", secret, key))]
+    MissingCredentialsKey {
+        secret: ObjectRef<Secret>,
+        key: String,
+    },
+    #[snafu(display("failed to retrieve the Secret {}", secret))]
+    GetSecret {
+        source: stackable_operator::error::Error,
+        secret: ObjectRef<Secret>,
+    },
+    #[snafu(display("failed to retrieve the ConfigMap {}", config_map))]
+    GetConfigMap {
+        source: stackable_operator::error::Error,
+        config_map: ObjectRef<ConfigMap>,
+    },
+    #[snafu(display(
+        "the ConfigMap {} does not contain the config snippet {}",
+        config_map,
+        key
+    ))]
+    MissingConfigSnippetKey {
+        config_map: ObjectRef<ConfigMap>,
+        key: String,
+    },
+}
+
+/// Returns a short SHA-256 hash of a credential which reveals whether it changed without
+/// disclosing it
+pub async fn credential_hash(
+    client: &Client,
+    credential: &SecretKeyRef,
+    namespace: &str,
+) -> Result<String, Error> {
+    let secret_ref = ObjectRef::<Secret>::new(&credential.name).within(namespace);
+    let secret = client
+        .get::<Secret>(&credential.name, Some(namespace))
+        .await
+        .context(GetCredentialsSecret {
+            secret: secret_ref.clone(),
+        })?;
+    let value = secret
+        .data
+        .unwrap_or_default()
+        .remove(&credential.key)
+        .context(MissingCredentialsKey {
+            secret: secret_ref,
+            key: &credential.key,
+        })?;
+    Ok(short_hash(Sha256::digest(&value.0)))
+}
+
+/// Returns the hash of a credential like [`credential_hash`], or `None` if its Secret or key does
+/// not exist
+pub async fn optional_credential_hash(
+    client: &Client,
+    credential: &SecretKeyRef,
+    namespace: &str,
+) -> Result<Option<String>, Error> {
+    match Api::<Secret>::namespaced(client.as_kube_client(), namespace)
+        .get(&credential.name)
+        .await
+    {
+        Ok(secret) => Ok(secret
+            .data
+            .unwrap_or_default()
+            .get(&credential.key)
+            .map(|value| short_hash(Sha256::digest(&value.0)))),
+        Err(stackable_operator::kube::Error::Api(response)) if response.code == 404 => Ok(None),
+        Err(source) => Err(Error::LookUpCredentialsSecret {
+            source,
+            secret: ObjectRef::<Secret>::new(&credential.name).within(namespace),
+        }),
+    }
+}
+
+/// Returns a short SHA-256 hash of the whole content of a Secret, used to roll pods which mount
+/// it when it changes
+pub async fn secret_hash(client: &Client, name: &str, namespace: &str) -> Result<String, Error> {
+    let secret = client
+        .get::<Secret>(name, Some(namespace))
+        .await
+        .context(GetSecret {
+            secret: ObjectRef::<Secret>::new(name).within(namespace),
+        })?;
+    let mut hasher = Sha256::new();
+    for (key, value) in secret.data.unwrap_or_default() {
+        hasher.update(key.as_bytes());
+        hasher.update([0]);
+        hasher.update(&value.0);
+        hasher.update([0]);
+    }
+    Ok(short_hash(hasher.finalize()))
+}
+
+/// Returns a short SHA-256 hash of the modules of the config snippets, used to roll the pods
+/// when a snippet changes
+pub async fn config_snippets_hash(
+    client: &Client,
+    snippets: &[ConfigSnippet],
+    namespace: &str,
+) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+    for snippet in snippets {
+        let config_map_ref = ObjectRef::<ConfigMap>::new(&snippet.config_map).within(namespace);
+        let config_map = client
+            .get::<ConfigMap>(&snippet.config_map, Some(namespace))
+            .await
+            .context(GetConfigMap {
+                config_map: config_map_ref.clone(),
+            })?;
+        let module = config_map
+            .data
+            .and_then(|mut data| data.remove(&snippet.key))
+            .context(MissingConfigSnippetKey {
+                config_map: config_map_ref,
+                key: &snippet.key,
+            })?;
+        hasher.update(snippet.key.as_bytes());
+        hasher.update([0]);
+        hasher.update(module.as_bytes());
+        hasher.update([0]);
+    }
+    Ok(short_hash(hasher.finalize()))
+}
+
+fn short_hash(digest: impl std::fmt::LowerHex) -> String {
+    let mut hash = format!("{digest:x}");
+    hash.truncate(16);
+    hash
 }
 
 pub enum JobState {
@@ -18,6 +205,140 @@ pub enum JobState {
     Failed,
 }
 
+/// Publishes an event for the given object. Failures are only logged because the events are merely
+/// informational.
+pub async fn publish_event<K>(
+    client: &Client,
+    object: &K,
+    type_: EventType,
+    reason: &str,
+    note: String,
+) where
+    K: Resource<DynamicType = ()>,
+{
+    let recorder = Recorder::new(
+        client.as_kube_client(),
+        Reporter {
+            controller: EVENT_REPORTER.to_string(),
+            instance: None,
+        },
+        object.object_ref(&()),
+    );
+    let event = Event {
+        type_,
+        reason: reason.to_string(),
+        note: Some(note),
+        action: reason.to_string(),
+        secondary: None,
+    };
+    if let Err(error) = recorder.publish(event).await {
+        tracing::warn!(%error, reason, "Failed to publish event");
+    }
+}
+
+/// Wraps a step of a Job script so that its failure is summarized in the termination message of the
+/// container
+pub fn with_failure_summary(step: &str, command: &str) -> String {
+    format!(
+        "{{ {command}; }} || {{ echo \"{step} failed with exit code $?\" > /dev/termination-log; exit 1; }}"
+    )
+}
+
+/// Collects the timestamps of a finished Job, its failure message if it failed, and the termination
+/// message of the failed container or of the succeeded one
+pub async fn job_details(client: &Client, job: &Job) -> Result<JobDetails, Error> {
+    let name = job.name();
+    let ns = job.namespace().unwrap_or_else(|| "default".to_string());
+    let status = job.status.clone().unwrap_or_default();
+    let failed_condition = status
+        .conditions
+        .iter()
+        .flatten()
+        .find(|condition| condition.type_ == "Failed" && condition.status == "True");
+
+    let pods = client
+        .list::<Pod>(
+            Some(&ns),
+            &ListParams::default().labels(&format!("job-name={name}")),
+        )
+        .await
+        .context(ListJobPods {
+            job: ObjectRef::<Job>::new(&name).within(&ns),
+        })?;
+    let failed = failed_condition.is_some();
+    let termination_message = pods
+        .iter()
+        .flat_map(|pod| pod.status.iter())
+        .flat_map(|status| {
+            status
+                .init_container_statuses
+                .iter()
+                .flatten()
+                .chain(status.container_statuses.iter().flatten())
+        })
+        .filter_map(|container_status| {
+            let terminated = container_status.state.as_ref()?.terminated.as_ref()?;
+            Some((container_status.name.as_str(), terminated))
+        })
+        .filter(|(_, terminated)| (terminated.exit_code != 0) == failed)
+        .filter_map(|(container, terminated)| {
+            let message = terminated
+                .message
+                .as_ref()
+                .map(|message| message.trim().to_string())
+                .filter(|message| !message.is_empty());
+            let message = if failed {
+                message.or_else(|| terminated.reason.clone())
+            } else {
+                message
+            };
+            // The end of the pip output does not tell that the Job failed before it started
+            let message = if failed && container == PYTHON_PACKAGES_CONTAINER {
+                message.map(|message| format!("installing the Python packages failed: {message}"))
+            } else {
+                message
+            };
+            Some((terminated.finished_at.clone(), message?))
+        })
+        .max_by_key(|(finished_at, _)| finished_at.clone().map(|Time(time)| time))
+        .map(|(_, message)| message);
+
+    Ok(JobDetails {
+        name,
+        start_time: status.start_time,
+        completion_time: status.completion_time.or_else(|| {
+            failed_condition.and_then(|condition| condition.last_transition_time.clone())
+        }),
+        failure_message: failed_condition.and_then(|condition| condition.message.clone()),
+        termination_message,
+    })
+}
+
+/// Appends the failure details of a Job to the note of an event
+pub fn job_failure_note(note: String, job: &JobDetails) -> String {
+    [
+        Some(note),
+        job.failure_message.clone(),
+        job.termination_message.clone(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(": ")
+}
+
+/// The spec of a Job with the retries, deadline and cleanup of the given settings or their defaults
+pub fn job_spec(template: PodTemplateSpec, settings: Option<&JobSettings>) -> JobSpec {
+    let settings = settings.cloned().unwrap_or_default();
+    JobSpec {
+        template,
+        backoff_limit: Some(settings.backoff_limit()),
+        active_deadline_seconds: Some(settings.active_deadline_seconds()),
+        ttl_seconds_after_finished: Some(settings.ttl_seconds_after_finished()),
+        ..JobSpec::default()
+    }
+}
+
 pub fn get_job_state(job: &Job) -> JobState {
     let conditions = job
         .status
@@ -44,10 +365,584 @@ pub fn superset_version(superset: &SupersetCluster) -> Result<&str, Error> {
     superset.spec.version.as_deref().context(ObjectHasNoVersion)
 }
 
-pub fn statsd_exporter_version(superset: &SupersetCluster) -> Result<&str, Error> {
-    superset
-        .spec
-        .statsd_exporter_version
-        .as_deref()
-        .context(ObjectHasNoStatsdExporterVersion)
+/// Adds the environment variables from which the SQLAlchemy URI of the metadata database is
+/// built: either the complete URI as `uri_env` or the credentials of an assembled URI
+pub fn add_metadata_database_env(
+    cb: &mut ContainerBuilder,
+    connection: &MetadataDatabaseConnection,
+    uri_env: &str,
+) {
+    match connection {
+        MetadataDatabaseConnection::Uri { uri, .. } => {
+            cb.add_env_var_from_secret(uri_env, &uri.name, &uri.key);
+        }
+        MetadataDatabaseConnection::Assembled(database) => {
+            cb.add_env_var_from_secret(
+                METADATA_DB_USERNAME_ENV,
+                &database.username.name,
+                &database.username.key,
+            );
+            cb.add_env_var_from_secret(
+                METADATA_DB_PASSWORD_ENV,
+                &database.password.name,
+                &database.password.key,
+            );
+        }
+    }
+}
+
+/// Sets the pull policy of all containers and the pull secrets of the pod
+pub fn add_image_pull_settings(pod: &mut PodSpec, product_image: &ProductImage) {
+    let pull_policy = product_image.pull_policy().to_string();
+    for container in pod
+        .containers
+        .iter_mut()
+        .chain(pod.init_containers.iter_mut().flatten())
+    {
+        container.image_pull_policy = Some(pull_policy.clone());
+    }
+    pod.image_pull_secrets = product_image.pull_secrets();
+}
+
+/// Mounts the certificates of the metadata database connection into the given container of the
+/// pod
+pub fn add_metadata_database_tls(
+    pod: &mut PodSpec,
+    container_name: &str,
+    connection: &MetadataDatabaseConnection,
+) {
+    let tls = match connection.tls() {
+        Some(tls) => tls,
+        None => return,
+    };
+
+    let mut volumes = Vec::new();
+    let ca_file = |key: &str| {
+        Some(vec![KeyToPath {
+            key: key.to_string(),
+            path: "ca.crt".to_string(),
+            ..KeyToPath::default()
+        }])
+    };
+    match &tls.ca {
+        Some(CaCertificate::ConfigMap { name, key }) => volumes.push((
+            Volume {
+                name: "metadata-db-ca".to_string(),
+                config_map: Some(ConfigMapVolumeSource {
+                    name: Some(name.clone()),
+                    items: ca_file(key),
+                    ..ConfigMapVolumeSource::default()
+                }),
+                ..Volume::default()
+            },
+            MetadataDatabaseTls::CA_DIR,
+        )),
+        Some(CaCertificate::Secret { name, key }) => volumes.push((
+            Volume {
+                name: "metadata-db-ca".to_string(),
+                secret: Some(SecretVolumeSource {
+                    secret_name: Some(name.clone()),
+                    items: ca_file(key),
+                    ..SecretVolumeSource::default()
+                }),
+                ..Volume::default()
+            },
+            MetadataDatabaseTls::CA_DIR,
+        )),
+        None => (),
+    }
+    if let Some(secret_name) = &tls.client_certificate_secret {
+        volumes.push((
+            Volume {
+                name: "metadata-db-client-tls".to_string(),
+                secret: Some(SecretVolumeSource {
+                    secret_name: Some(secret_name.clone()),
+                    // The database clients refuse keys which are readable by others
+                    default_mode: Some(0o640),
+                    ..SecretVolumeSource::default()
+                }),
+                ..Volume::default()
+            },
+            MetadataDatabaseTls::CLIENT_CERTIFICATE_DIR,
+        ));
+        // Grants the Stackable user group access to the key
+        pod.security_context
+            .get_or_insert_with(PodSecurityContext::default)
+            .fs_group = Some(1000);
+    }
+
+    for container in pod
+        .containers
+        .iter_mut()
+        .filter(|container| container.name == container_name)
+    {
+        let mounts = container.volume_mounts.get_or_insert_with(Vec::new);
+        for (volume, mount_path) in &volumes {
+            mounts.push(VolumeMount {
+                name: volume.name.clone(),
+                mount_path: mount_path.to_string(),
+                read_only: Some(true),
+                ..VolumeMount::default()
+            });
+        }
+    }
+    pod.volumes
+        .get_or_insert_with(Vec::new)
+        .extend(volumes.into_iter().map(|(volume, _)| volume));
+}
+
+/// Installs the Python packages with an init container into a volume which is added to the
+/// `PYTHONPATH` of the given container. The init container uses the image of that container.
+pub fn add_python_packages(
+    pod: &mut PodSpec,
+    container_name: &str,
+    python_packages: &PythonPackages,
+) {
+    let image = pod
+        .containers
+        .iter()
+        .find(|container| container.name == container_name)
+        .and_then(|container| container.image.clone());
+
+    let mut volumes = vec![Volume {
+        name: "python-packages".to_string(),
+        empty_dir: Some(EmptyDirVolumeSource::default()),
+        ..Volume::default()
+    }];
+    let mut install_mounts = vec![VolumeMount {
+        name: "python-packages".to_string(),
+        mount_path: PYTHON_PACKAGES_DIR.to_string(),
+        ..VolumeMount::default()
+    }];
+    let mut pip_args = vec![
+        "pip install --no-cache-dir --disable-pip-version-check".to_string(),
+        format!("--target {PYTHON_PACKAGES_DIR}"),
+    ];
+    if let Some(wheelhouse) = &python_packages.wheelhouse {
+        volumes.push(Volume {
+            name: "wheelhouse".to_string(),
+            persistent_volume_claim: match wheelhouse {
+                Wheelhouse::PersistentVolumeClaim { claim_name } => {
+                    Some(PersistentVolumeClaimVolumeSource {
+                        claim_name: claim_name.clone(),
+                        read_only: Some(true),
+                    })
+                }
+                Wheelhouse::ConfigMap { .. } => None,
+            },
+            config_map: match wheelhouse {
+                Wheelhouse::ConfigMap { name } => Some(ConfigMapVolumeSource {
+                    name: Some(name.clone()),
+                    ..ConfigMapVolumeSource::default()
+                }),
+                Wheelhouse::PersistentVolumeClaim { .. } => None,
+            },
+            ..Volume::default()
+        });
+        install_mounts.push(VolumeMount {
+            name: "wheelhouse".to_string(),
+            mount_path: WHEELHOUSE_DIR.to_string(),
+            read_only: Some(true),
+            ..VolumeMount::default()
+        });
+        pip_args.push(format!("--no-index --find-links {WHEELHOUSE_DIR}"));
+        if python_packages.packages.is_empty() {
+            pip_args.push(format!("{WHEELHOUSE_DIR}/*.whl"));
+        }
+    }
+    pip_args.extend(
+        python_packages
+            .packages
+            .iter()
+            .map(|package| shell_quote(package)),
+    );
+
+    let mut install_container = ContainerBuilder::new(PYTHON_PACKAGES_CONTAINER)
+        .command(vec!["/bin/bash".to_string()])
+        .args(vec![
+            String::from("-euo"),
+            String::from("pipefail"),
+            String::from("-c"),
+            pip_args.join(" "),
+        ])
+        .build();
+    install_container.image = image;
+    install_container.volume_mounts = Some(install_mounts);
+    // The end of the pip output is reported in the status of the cluster
+    install_container.termination_message_policy = Some("FallbackToLogsOnError".to_string());
+    pod.init_containers
+        .get_or_insert_with(Vec::new)
+        .push(install_container);
+
+    for container in pod
+        .containers
+        .iter_mut()
+        .filter(|container| container.name == container_name)
+    {
+        container
+            .volume_mounts
+            .get_or_insert_with(Vec::new)
+            .push(VolumeMount {
+                name: "python-packages".to_string(),
+                mount_path: PYTHON_PACKAGES_DIR.to_string(),
+                read_only: Some(true),
+                ..VolumeMount::default()
+            });
+        container.env.get_or_insert_with(Vec::new).push(EnvVar {
+            name: "PYTHONPATH".to_string(),
+            value: Some(format!("{PYTHONPATH}:{PYTHON_PACKAGES_DIR}")),
+            ..EnvVar::default()
+        });
+    }
+    pod.volumes.get_or_insert_with(Vec::new).extend(volumes);
+}
+
+/// Quotes a string for bash
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Returns a Python expression which evaluates to the SQLAlchemy URI of the metadata database,
+/// given the environment variables of [`add_metadata_database_env`].
+///
+/// The expression requires `import os` and `from urllib.parse import quote`.
+pub fn metadata_database_uri_expression(
+    connection: &MetadataDatabaseConnection,
+    uri_env: &str,
+) -> String {
+    match connection {
+        MetadataDatabaseConnection::Uri { tls: None, .. } => format!("os.environ.get('{uri_env}')"),
+        // The database type of a URI from a Secret is only known at runtime, the queries are
+        // percent-encoded and cannot contain quotes
+        MetadataDatabaseConnection::Uri { tls: Some(tls), .. } => format!(
+            "(lambda uri: uri + ('&' if '?' in uri else '?') + ('{}' if uri.startswith('postgres') else '{}'))(os.environ['{uri_env}'])",
+            tls.uri_query(DatabaseType::Postgresql),
+            tls.uri_query(DatabaseType::Mysql),
+        ),
+        // The credentials are only known at runtime, the remaining components are already
+        // percent-encoded and cannot contain quotes
+        MetadataDatabaseConnection::Assembled(database) => format!(
+            "'{}' + quote(os.environ['{METADATA_DB_USERNAME_ENV}'], safe='') + ':' + quote(os.environ['{METADATA_DB_PASSWORD_ENV}'], safe='') + '{}'",
+            database.uri_scheme(),
+            database.uri_location(),
+        ),
+    }
+}
+
+/// Returns shell commands which export the assembled SQLAlchemy URI of the metadata database as
+/// `$DATABASE_URI`, for containers without Python such as the database client tools.
+///
+/// A URI which is read from a Secret into `$DATABASE_URI` directly only gets the TLS parameters
+/// appended.
+pub fn metadata_database_uri_commands(connection: &MetadataDatabaseConnection) -> Vec<String> {
+    match connection {
+        MetadataDatabaseConnection::Uri { tls: None, .. } => Vec::new(),
+        MetadataDatabaseConnection::Uri { tls: Some(tls), .. } => vec![
+            String::from(r#"case "$DATABASE_URI" in *\?*) sep='&' ;; *) sep='?' ;; esac"#),
+            format!(
+                r#"case "$DATABASE_URI" in postgres*) query='{}' ;; *) query='{}' ;; esac"#,
+                tls.uri_query(DatabaseType::Postgresql),
+                tls.uri_query(DatabaseType::Mysql),
+            ),
+            String::from(r#"export DATABASE_URI="$DATABASE_URI$sep$query""#),
+        ],
+        MetadataDatabaseConnection::Assembled(database) => vec![
+            String::from(
+                r#"urlencode() { local LC_ALL=C s="$1" i c; for ((i = 0; i < ${#s}; i++)); do c="${s:i:1}"; case "$c" in [a-zA-Z0-9.~_-]) printf '%s' "$c" ;; *) printf '%%%02X' "'$c" ;; esac; done; }"#,
+            ),
+            format!(
+                r#"export DATABASE_URI="{}$(urlencode "${METADATA_DB_USERNAME_ENV}"):$(urlencode "${METADATA_DB_PASSWORD_ENV}"){}""#,
+                database.uri_scheme(),
+                database.uri_location(),
+            ),
+        ],
+    }
+}
+
+/// Adds the environment variables which the `superset_config.py` of
+/// [`superset_job_config`] reads
+pub fn add_superset_config_env(cb: &mut ContainerBuilder, credentials: &ResolvedCredentials) {
+    cb.add_env_var_from_secret(
+        "SECRET_KEY",
+        &credentials.secret_key.name,
+        &credentials.secret_key.key,
+    );
+    add_metadata_database_env(cb, &credentials.metadata_database, "DATABASE_URI");
+}
+
+/// Returns the `superset_config.py` for the Superset CLI in the Jobs, which is mounted from a
+/// ConfigMap with [`add_job_config_volume`].
+///
+/// The secret key must match the one of the webserver, otherwise the Jobs could not decrypt the
+/// secrets stored in the metadata database. The config snippets are added like in the webserver,
+/// e.g. `superset init` needs the `CUSTOM_SECURITY_MANAGER`.
+pub fn superset_job_config(
+    credentials: &ResolvedCredentials,
+    snippets: &[ConfigSnippet],
+) -> Result<String, stackable_superset_crd::Error> {
+    let mut config = format!(
+        "import os\n\
+         from urllib.parse import quote\n\
+         \n\
+         SECRET_KEY = os.environ.get('SECRET_KEY')\n\
+         SQLALCHEMY_DATABASE_URI = {}\n",
+        metadata_database_uri_expression(&credentials.metadata_database, "DATABASE_URI")
+    );
+    if !snippets.is_empty() {
+        config.push_str(&config_snippets_code(snippets)?);
+    }
+    Ok(config)
+}
+
+/// Mounts the ConfigMap with the generated files of a Job on the `PYTHONPATH` of the given
+/// container, so that the `superset_config.py` is picked up by the Superset CLI, and the config
+/// snippets which it imports
+pub fn add_job_config_volume(
+    pod: &mut PodSpec,
+    container_name: &str,
+    config_map_name: &str,
+    snippets: &[ConfigSnippet],
+) {
+    for container in pod
+        .containers
+        .iter_mut()
+        .filter(|container| container.name == container_name)
+    {
+        let volume_mounts = container.volume_mounts.get_or_insert_with(Vec::new);
+        volume_mounts.push(VolumeMount {
+            name: JOB_CONFIG_VOLUME.to_string(),
+            mount_path: PYTHONPATH.to_string(),
+            read_only: Some(true),
+            ..VolumeMount::default()
+        });
+        if !snippets.is_empty() {
+            volume_mounts.push(VolumeMount {
+                name: CONFIG_SNIPPETS_VOLUME.to_string(),
+                mount_path: CONFIG_SNIPPETS_DIR.to_string(),
+                read_only: Some(true),
+                ..VolumeMount::default()
+            });
+        }
+    }
+    let volumes = pod.volumes.get_or_insert_with(Vec::new);
+    volumes.push(Volume {
+        name: JOB_CONFIG_VOLUME.to_string(),
+        config_map: Some(ConfigMapVolumeSource {
+            name: Some(config_map_name.to_string()),
+            ..ConfigMapVolumeSource::default()
+        }),
+        ..Volume::default()
+    });
+    if !snippets.is_empty() {
+        volumes.push(config_snippets_volume(snippets));
+    }
+}
+
+/// Returns the Python code which adds the config snippets at the end of `superset_config.py`.
+/// Every snippet is preceded by a comment which names the ConfigMap it comes from.
+pub fn config_snippets_code(
+    snippets: &[ConfigSnippet],
+) -> Result<String, stackable_superset_crd::Error> {
+    let mut lines = vec![
+        String::new(),
+        "# Config snippets from spec.configSnippets".to_string(),
+        "import sys".to_string(),
+        format!("sys.path.append('{CONFIG_SNIPPETS_DIR}')"),
+    ];
+    for snippet in snippets {
+        lines.push(format!(
+            "# Key {} of ConfigMap {}",
+            snippet.key, snippet.config_map
+        ));
+        lines.push(match snippet.mode {
+            ConfigSnippetMode::Import => format!("from {} import *", snippet.module_name()?),
+            ConfigSnippetMode::Append => format!(
+                "exec(compile(open('{CONFIG_SNIPPETS_DIR}/{key}').read(), '{key}', 'exec'))",
+                key = snippet.key
+            ),
+        });
+    }
+    lines.push(String::new());
+    Ok(lines.join("\n"))
+}
+
+/// The volume with the modules of all config snippets, mounted at [`CONFIG_SNIPPETS_DIR`]
+pub fn config_snippets_volume(snippets: &[ConfigSnippet]) -> Volume {
+    Volume {
+        name: CONFIG_SNIPPETS_VOLUME.to_string(),
+        projected: Some(ProjectedVolumeSource {
+            sources: Some(
+                snippets
+                    .iter()
+                    .map(|snippet| VolumeProjection {
+                        config_map: Some(ConfigMapProjection {
+                            name: Some(snippet.config_map.clone()),
+                            items: Some(vec![KeyToPath {
+                                key: snippet.key.clone(),
+                                path: snippet.key.clone(),
+                                ..KeyToPath::default()
+                            }]),
+                            ..ConfigMapProjection::default()
+                        }),
+                        ..VolumeProjection::default()
+                    })
+                    .collect(),
+            ),
+            ..ProjectedVolumeSource::default()
+        }),
+        ..Volume::default()
+    }
+}
+
+/// Returns shell commands which derive the connection parameters of the database client tools
+/// from the SQLAlchemy URI in `$DATABASE_URI`.
+///
+/// PostgreSQL clients get a libpq URI in `$uri`, MySQL clients get `$mysql_args` and `$database`.
+pub fn database_client_preamble(
+    db_type: DatabaseType,
+    connection: &MetadataDatabaseConnection,
+) -> Vec<String> {
+    let mut preamble = match db_type {
+        DatabaseType::Postgresql => vec![
+            // libpq does not understand the SQLAlchemy driver suffix, e.g. `postgresql+psycopg2`
+            String::from(
+                r#"uri="$(echo "$DATABASE_URI" | sed -E 's|^([a-z]+)\+[^:]*://|\1://|')""#,
+            ),
+        ],
+        DatabaseType::Mysql => vec![
+            String::from(
+                r#"re='^[a-z]+(\+[^:]*)?://([^:@/]+)(:([^@]*))?@([^:/?]+)(:([0-9]+))?/([^?]+)'"#,
+            ),
+            String::from(
+                r#"[[ "$DATABASE_URI" =~ $re ]] || { echo "unsupported database URI" >&2; exit 1; }"#,
+            ),
+            String::from(r#"urldecode() { printf '%b' "${1//%/\\x}"; }"#),
+            String::from(r#"export MYSQL_PWD="$(urldecode "${BASH_REMATCH[4]}")""#),
+            String::from(
+                r#"mysql_args=(--host="${BASH_REMATCH[5]}" --port="${BASH_REMATCH[7]:-3306}" --user="$(urldecode "${BASH_REMATCH[2]}")")"#,
+            ),
+            String::from(r#"database="$(urldecode "${BASH_REMATCH[8]}")""#),
+        ],
+    };
+
+    // libpq reads the TLS settings from the URI, the MySQL client needs them as arguments
+    if let (DatabaseType::Mysql, Some(tls)) = (db_type, connection.tls()) {
+        let mut args = Vec::new();
+        if let Some(ssl_mode) = &tls.ssl_mode {
+            args.push(format!("--ssl-mode={ssl_mode}"));
+        }
+        if tls.ca.is_some() {
+            args.push(format!("--ssl-ca={}", MetadataDatabaseTls::CA_FILE));
+        }
+        if tls.client_certificate_secret.is_some() {
+            args.push(format!(
+                "--ssl-cert={}/tls.crt",
+                MetadataDatabaseTls::CLIENT_CERTIFICATE_DIR
+            ));
+            args.push(format!(
+                "--ssl-key={}/tls.key",
+                MetadataDatabaseTls::CLIENT_CERTIFICATE_DIR
+            ));
+        }
+        if !args.is_empty() {
+            preamble.push(format!("mysql_args+=({})", args.join(" ")));
+        }
+    }
+
+    preamble
+}
+
+/// Checks that all Secrets referenced by the credentials exist and contain the referenced keys,
+/// returns a description of the first missing Secret or key
+pub async fn check_credentials(
+    client: &Client,
+    credentials: &ResolvedCredentials,
+    namespace: &str,
+) -> Result<Option<String>, Error> {
+    let mut keys_by_secret = BTreeMap::<&str, Vec<&str>>::new();
+    for SecretKeyRef { name, key } in credentials.secret_key_refs() {
+        keys_by_secret
+            .entry(name.as_str())
+            .or_default()
+            .push(key.as_str());
+    }
+
+    for (secret_name, keys) in keys_by_secret {
+        let secret_ref = ObjectRef::<Secret>::new(secret_name).within(namespace);
+        let secret = match Api::<Secret>::namespaced(client.as_kube_client(), namespace)
+            .get(secret_name)
+            .await
+        {
+            Ok(secret) => secret,
+            Err(stackable_operator::kube::Error::Api(response)) if response.code == 404 => {
+                return Ok(Some(format!("the Secret {secret_ref} does not exist")));
+            }
+            Err(source) => {
+                return Err(Error::LookUpCredentialsSecret {
+                    source,
+                    secret: secret_ref,
+                })
+            }
+        };
+        let data = secret.data.unwrap_or_default();
+        if let Some(key) = keys.into_iter().find(|key| !data.contains_key(*key)) {
+            return Ok(Some(format!(
+                "the Secret {secret_ref} does not contain the key {key}"
+            )));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stackable_superset_crd::MetadataDatabase;
+
+    /// A password with all characters which are special in URIs, Python or shell strings
+    const PASSWORD: &str = "p@ss:w/o%r'd\"$x";
+
+    fn assembled_connection() -> MetadataDatabaseConnection {
+        MetadataDatabaseConnection::Assembled(MetadataDatabase {
+            db_type: DatabaseType::Postgresql,
+            host: "superset-postgresql".to_string(),
+            port: None,
+            database: "superset".to_string(),
+            options: BTreeMap::new(),
+            username: SecretKeyRef::new("metadata-db", "username"),
+            password: SecretKeyRef::new("metadata-db", "password"),
+            tls: None,
+        })
+    }
+
+    #[test]
+    fn shell_quote_keeps_special_characters_literal() {
+        assert_eq!(shell_quote(PASSWORD), r#"'p@ss:w/o%r'\''d"$x'"#);
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn uri_expression_reads_the_uri_from_the_environment() {
+        let connection = MetadataDatabaseConnection::Uri {
+            uri: SecretKeyRef::new("credentials", "connections.sqlalchemyDatabaseUri"),
+            tls: None,
+        };
+        assert_eq!(
+            metadata_database_uri_expression(&connection, "DATABASE_URI"),
+            "os.environ.get('DATABASE_URI')"
+        );
+    }
+
+    #[test]
+    fn uri_expression_quotes_the_credentials_at_runtime() {
+        // The password is never part of the expression, it is read from the environment and
+        // percent-encoded by Python without any safe characters
+        let expression = metadata_database_uri_expression(&assembled_connection(), "DATABASE_URI");
+        assert_eq!(
+            expression,
+            "'postgresql://' + quote(os.environ['METADATA_DB_USERNAME'], safe='') + ':' + quote(os.environ['METADATA_DB_PASSWORD'], safe='') + '@superset-postgresql:5432/superset'"
+        );
+        assert!(!expression.contains(PASSWORD));
+    }
 }